@@ -0,0 +1,1072 @@
+use std::{
+    cmp::Reverse,
+    ffi::OsString,
+    fs::{self, FileTimes},
+    io::{self, BufRead, Write},
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+    time::SystemTime,
+};
+
+use globset::GlobMatcher;
+use regex::Regex;
+
+use crate::{
+    DuplicateGroup,
+    config::Config,
+    edit,
+    hash::FileHash,
+    interrupt::{self, StopReason},
+    journal::{Entry, Journal},
+    log, platform, report,
+    units::{format_size, format_time},
+    verify::files_equal,
+    walk::{FileEntry, relative_to_root},
+};
+
+/// What is done with the copies of a group that are not kept.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    Delete,
+    /// Send them to the trash of the desktop, where they can still be restored from.
+    Trash,
+    /// Replace them with links to a kept copy.
+    Link(LinkKind),
+    /// Move them below this quarantine directory, at their path from the scan root.
+    Move(PathBuf),
+}
+
+impl Action {
+    /// What is done to a file, for the messages about it.
+    fn verb(&self) -> &'static str {
+        match self {
+            Action::Delete => "delete",
+            Action::Trash => "trash",
+            Action::Link(LinkKind::Hard) => "hardlink",
+            Action::Link(LinkKind::Soft(_)) => "symlink",
+            Action::Link(LinkKind::Reflink) => "reflink",
+            Action::Move(_) => "move",
+        }
+    }
+
+    /// What was done to a file, for the messages about it.
+    fn past_tense(&self) -> &'static str {
+        match self {
+            Action::Delete => "Deleted",
+            Action::Trash => "Trashed",
+            Action::Link(_) => "Linked",
+            Action::Move(_) => "Moved",
+        }
+    }
+}
+
+/// Kind of link copies are replaced with by `--link`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkKind {
+    /// A hardlink, only possible within a single file system.
+    Hard,
+    /// A symlink, which may point to another file system.
+    Soft(LinkStyle),
+    /// A copy-on-write clone, an independent file sharing its extents with the kept
+    /// copy. Only possible within a single file system supporting it.
+    Reflink,
+}
+
+/// How symlinks made by `--link soft` point to the kept copy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkStyle {
+    /// Through its absolute path.
+    Absolute,
+    /// Through its path from the directory of the link, surviving the whole tree being
+    /// moved elsewhere.
+    Relative,
+}
+
+impl FromStr for LinkStyle {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "absolute" => Ok(LinkStyle::Absolute),
+            "relative" => Ok(LinkStyle::Relative),
+            _ => Err(format!(
+                "Unknown link style `{name}`, expected one of: absolute, relative"
+            )),
+        }
+    }
+}
+
+impl FromStr for LinkKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "hard" => Ok(LinkKind::Hard),
+            "soft" => Ok(LinkKind::Soft(LinkStyle::Absolute)),
+            "reflink" => Ok(LinkKind::Reflink),
+            _ => Err(format!(
+                "Unknown link kind `{name}`, expected one of: hard, soft, reflink"
+            )),
+        }
+    }
+}
+
+/// How the copies kept in every group are chosen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Selection {
+    /// Asking for each group, the way fdupes does.
+    Interactive,
+    /// The one copy picked by a policy, without asking.
+    Keep(KeepPolicy),
+    /// Edited for every group at once in `$EDITOR`, starting from what the policy picks.
+    Edit(KeepPolicy),
+}
+
+/// Which single copy of a group is kept when nobody is asked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeepPolicy {
+    /// The most recently modified copy.
+    Newest,
+    /// The least recently modified copy.
+    Oldest,
+    /// The copy listed first in the report.
+    First,
+    /// The copy with the fewest characters in its path.
+    ShortestPath,
+    /// The copy with the most characters in its path.
+    LongestPath,
+}
+
+impl FromStr for KeepPolicy {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "newest" => Ok(KeepPolicy::Newest),
+            "oldest" => Ok(KeepPolicy::Oldest),
+            "first" => Ok(KeepPolicy::First),
+            "shortest-path" => Ok(KeepPolicy::ShortestPath),
+            "longest-path" => Ok(KeepPolicy::LongestPath),
+            _ => Err(format!(
+                "Unknown keep policy `{name}`, expected one of: newest, oldest, first, shortest-path, longest-path"
+            )),
+        }
+    }
+}
+
+impl KeepPolicy {
+    /// Index of the copy of `group` kept, among the copies in the reference directory or
+    /// else the protected copies if there are any, then among those in the most preferred
+    /// directory holding one. Ties go to the copy listed first, and copies whose
+    /// modification time is unknown are only kept when no other one is.
+    pub fn choose(self, group: &DuplicateGroup, config: &Config) -> usize {
+        let protected = &config.protected;
+        // Originals of the reference directory come before anything else.
+        let any_reference = group
+            .files
+            .iter()
+            .any(|file| config.in_reference(&file.path));
+        let any_protected = group.files.iter().any(|file| protected.covers(&file.path));
+        let keepable = |file: &FileEntry| match (any_reference, any_protected) {
+            (true, _) => config.in_reference(&file.path),
+            (false, true) => protected.covers(&file.path),
+            (false, false) => true,
+        };
+        let rank = |file: &FileEntry| preference(&file.path, &config.preferred);
+        let best_rank = group
+            .files
+            .iter()
+            .filter(|file| keepable(file))
+            .map(rank)
+            .min();
+        let files = group
+            .files
+            .iter()
+            .enumerate()
+            .filter(|(_, file)| keepable(file) && Some(rank(file)) == best_rank);
+        let length = |file: &FileEntry| file.path.as_os_str().len();
+        let chosen = match self {
+            KeepPolicy::First => files.map(|(position, _)| position).next(),
+            KeepPolicy::Newest => files
+                .filter_map(|(position, file)| Some((position, file.modified?)))
+                .min_by_key(|&(position, modified)| (Reverse(modified), position))
+                .map(|(position, _)| position),
+            KeepPolicy::Oldest => files
+                .filter_map(|(position, file)| Some((position, file.modified?)))
+                .min_by_key(|&(position, modified)| (modified, position))
+                .map(|(position, _)| position),
+            KeepPolicy::ShortestPath => files
+                .min_by_key(|&(position, file)| (length(file), position))
+                .map(|(position, _)| position),
+            KeepPolicy::LongestPath => files
+                .min_by_key(|&(position, file)| (Reverse(length(file)), position))
+                .map(|(position, _)| position),
+        };
+        chosen.unwrap_or(0)
+    }
+}
+
+/// Position of the copy of `group` that is kept, as far as it is known before acting:
+/// the one the keep policy chooses, or the first one when nothing is done. `None` when
+/// the user chooses as they go.
+pub fn suggested_keep(group: &DuplicateGroup, config: &Config) -> Option<usize> {
+    match &config.action {
+        Some((_, Selection::Keep(policy) | Selection::Edit(policy))) => {
+            Some(policy.choose(group, config))
+        }
+        Some((_, Selection::Interactive)) => None,
+        None => Some(0),
+    }
+}
+
+/// Position of the first of the `preferred` directories `path` is inside of, past them
+/// all when it is in none.
+fn preference(path: &Path, preferred: &[PathBuf]) -> usize {
+    if preferred.is_empty() {
+        return 0;
+    }
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    preferred
+        .iter()
+        .position(|directory| path.starts_with(directory))
+        .unwrap_or(preferred.len())
+}
+
+/// Shell a `--script` is written for, deleting the copies not kept when run later.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shell {
+    /// A POSIX shell script of `rm` commands.
+    Sh,
+    /// A PowerShell script of `Remove-Item` commands.
+    PowerShell,
+}
+
+impl FromStr for Shell {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "sh" => Ok(Shell::Sh),
+            "powershell" | "pwsh" => Ok(Shell::PowerShell),
+            _ => Err(format!(
+                "Unknown shell `{name}`, expected one of: sh, powershell"
+            )),
+        }
+    }
+}
+
+impl Shell {
+    /// First lines of the script.
+    fn header(self) -> &'static str {
+        match self {
+            Shell::Sh => "#!/bin/sh\n# Written by fdup, review before running.\n",
+            Shell::PowerShell => "# Written by fdup, review before running.\n",
+        }
+    }
+
+    /// Command deleting the file at `path`.
+    fn remove_command(self, path: &Path) -> String {
+        let path = path.to_string_lossy();
+        match self {
+            Shell::Sh => format!("rm -- '{}'", path.replace('\'', r"'\''")),
+            // PowerShell also takes typographic single quotes for plain ones, any of them
+            // is escaped by doubling it.
+            Shell::PowerShell => {
+                let quoted: String = path
+                    .chars()
+                    .flat_map(|c| match c {
+                        '\'' | '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}' => vec![c, c],
+                        c => vec![c],
+                    })
+                    .collect();
+                format!("Remove-Item -LiteralPath '{quoted}'")
+            }
+        }
+    }
+
+    /// Comment saying `path` is kept, both shells start comments with `#`. Line breaks
+    /// would end the comment, so they are replaced.
+    fn keep_comment(self, path: &Path) -> String {
+        format!(
+            "\n# Keeping {}",
+            path.to_string_lossy().replace(['\n', '\r'], "?")
+        )
+    }
+}
+
+/// Files that must never be removed, whatever copies are chosen to be kept.
+#[derive(Default)]
+pub struct Protected {
+    /// Pattern matched against the whole path of files, such as `**/originals/**`.
+    pub pattern: Option<GlobMatcher>,
+    /// Expression matched anywhere in the whole path of files.
+    pub expression: Option<Regex>,
+}
+
+impl Protected {
+    /// Whether the file at `path` must be left alone.
+    fn covers(&self, path: &Path) -> bool {
+        self.pattern
+            .as_ref()
+            .is_some_and(|pattern| pattern.is_match(path))
+            || self
+                .expression
+                .as_ref()
+                .is_some_and(|expression| expression.is_match(&path.to_string_lossy()))
+    }
+}
+
+/// Metadata of the copies acted upon, merged onto the kept one with `--merge-metadata`.
+#[derive(Default)]
+struct MergedMetadata {
+    /// Latest modification time.
+    modified: Option<SystemTime>,
+    /// Earliest creation time.
+    created: Option<SystemTime>,
+    /// Every extended attribute, such as Finder tags, the first copy holding one wins.
+    xattrs: Vec<(OsString, Vec<u8>)>,
+}
+
+impl MergedMetadata {
+    /// Metadata of the file at `path` alone, whatever of it can be read.
+    fn of(path: &Path) -> MergedMetadata {
+        let meta = fs::metadata(path).ok();
+        let xattrs = platform::xattrs(path).unwrap_or_else(|err| {
+            log::warn!(
+                "Error reading extended attributes: `{}` {err}",
+                path.to_string_lossy()
+            );
+            Vec::new()
+        });
+        MergedMetadata {
+            modified: meta.as_ref().and_then(|meta| meta.modified().ok()),
+            created: meta.as_ref().and_then(|meta| meta.created().ok()),
+            xattrs,
+        }
+    }
+
+    /// Take in the metadata of another copy.
+    fn merge(&mut self, other: MergedMetadata) {
+        self.modified = self.modified.max(other.modified);
+        self.created = match (self.created, other.created) {
+            (Some(first), Some(second)) => Some(first.min(second)),
+            (first, second) => first.or(second),
+        };
+        for (name, value) in other.xattrs {
+            if !self.xattrs.iter().any(|(known, _)| *known == name) {
+                self.xattrs.push((name, value));
+            }
+        }
+    }
+
+    /// Give the file at `path` the latest modification time, the earliest creation time
+    /// where it can be set, and the extended attributes it lacks.
+    fn apply_to(&self, path: &Path) -> io::Result<()> {
+        let meta = fs::metadata(path)?;
+        let mut times = FileTimes::new();
+        if let Some(modified) = self.modified
+            && meta.modified().is_ok_and(|current| modified > current)
+        {
+            times = times.set_modified(modified);
+        }
+        if let Some(created) = self.created
+            && meta.created().is_ok_and(|current| created < current)
+        {
+            times = platform::with_created(times, created);
+        }
+        // Setting times takes write access on Windows, only ownership elsewhere.
+        let file = fs::File::options()
+            .write(true)
+            .open(path)
+            .or_else(|_| fs::File::open(path))?;
+        file.set_times(times)?;
+        let present = platform::xattrs(path)?;
+        for (name, value) in &self.xattrs {
+            if present.iter().any(|(known, _)| known == name) {
+                continue;
+            }
+            if let Err(err) = platform::set_xattr(path, name, value) {
+                log::warn!(
+                    "Error copying extended attribute `{}` to `{}` {err}",
+                    name.to_string_lossy(),
+                    path.to_string_lossy()
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Files acted upon and the space that freed, over every group.
+#[derive(Default)]
+struct Totals {
+    files: usize,
+    bytes: u64,
+}
+
+/// Apply the action of `config` to every copy of `groups` not kept, as chosen by its
+/// selection. Groups where a protected file would not be kept are skipped, and so are
+/// those freeing less than `--min-savings`.
+pub fn apply(groups: &[DuplicateGroup], config: &Config) {
+    let Some((action, selection)) = &config.action else {
+        return;
+    };
+    let edited = match selection {
+        Selection::Edit(policy) => match edit::edit_selection(groups, *policy, config) {
+            Ok(Some(edited)) => edited,
+            Ok(None) => {
+                eprintln!("Selection emptied, nothing done");
+                return;
+            }
+            Err(err) => {
+                log::error!("{err}");
+                return;
+            }
+        },
+        _ => Vec::new(),
+    };
+    let protected = &config.protected;
+    // Nothing is touched without a journal to undo it from, when one was asked for.
+    let acting = !config.dry_run && config.script.is_none();
+    let mut journal = match &config.journal_path {
+        Some(location) if acting => match Journal::open(location) {
+            Ok(journal) => Some(journal),
+            Err(err) => {
+                log::error!(
+                    "Error opening journal: `{}` {err}",
+                    location.to_string_lossy()
+                );
+                return;
+            }
+        },
+        _ => None,
+    };
+    if let Some(shell) = config.script {
+        report::emit(|out| write!(out, "{}", shell.header()));
+    }
+    let mut totals = Totals::default();
+    let mut input = io::stdin().lock();
+    // Positions kept in every remaining group once answered `all`.
+    let mut pattern: Option<Vec<usize>> = None;
+    for (index, group) in groups.iter().enumerate() {
+        // Running out of time only cuts the scan short, never the actions that follow it.
+        if interrupt::stop_reason() == Some(StopReason::Interrupted) {
+            break;
+        }
+        if config
+            .min_savings
+            .is_some_and(|min| group.wasted_bytes() < min)
+        {
+            continue;
+        }
+        let mut kept = match *selection {
+            Selection::Interactive => match &pattern {
+                Some(pattern) => {
+                    let kept: Vec<usize> = pattern
+                        .iter()
+                        .copied()
+                        .filter(|&position| position < group.files.len())
+                        .collect();
+                    if kept.is_empty() {
+                        log::warn!(
+                            "Skipping group {}, it is too small to keep the same copies",
+                            index + 1
+                        );
+                        continue;
+                    }
+                    for &position in &kept {
+                        eprintln!("Keeping `{}`", group.files[position].path.to_string_lossy());
+                    }
+                    kept
+                }
+                None => match ask_kept(&mut input, group, index, groups.len()) {
+                    Ok(Choice::Keep(kept)) => kept,
+                    Ok(Choice::Skip) => continue,
+                    Ok(Choice::Quit) => break,
+                    Err(err) => {
+                        log::error!("Error reading the answer: {err}");
+                        break;
+                    }
+                },
+            },
+            Selection::Keep(policy) => {
+                let kept = policy.choose(group, config);
+                eprintln!("Keeping `{}`", group.files[kept].path.to_string_lossy());
+                vec![kept]
+            }
+            Selection::Edit(_) => edited[index].clone(),
+        };
+        let Some(removed) = removed_copies(group, &mut kept, |path| config.in_reference(path))
+        else {
+            log::error!(
+                "Refusing to {} every copy of group {}, stopping",
+                action.verb(),
+                index + 1
+            );
+            break;
+        };
+        if removed.is_empty() {
+            continue;
+        }
+        if let Some(file) = removed.iter().find(|file| protected.covers(&file.path)) {
+            log::warn!(
+                "Skipping group {}, `{}` is protected by --keep-pattern or --keep-regex",
+                index + 1,
+                file.path.to_string_lossy()
+            );
+            continue;
+        }
+        if *selection == Selection::Interactive && pattern.is_none() {
+            match confirm(&mut input, action, removed.len()) {
+                Ok(Confirmation::Yes) => {}
+                Ok(Confirmation::No) => continue,
+                Ok(Confirmation::All) => pattern = Some(kept.clone()),
+                Ok(Confirmation::Quit) => break,
+                Err(err) => {
+                    log::error!("Error reading the answer: {err}");
+                    break;
+                }
+            }
+        }
+        let survivors: Vec<&FileEntry> = kept
+            .iter()
+            .map(|&position| &group.files[position])
+            .collect();
+        act_on_group(
+            config,
+            action,
+            &group.hash,
+            &survivors,
+            &removed,
+            &mut journal,
+            &mut totals,
+        );
+    }
+    if config.script.is_some() {
+        eprintln!(
+            "Wrote commands to {} {} files, freeing {}",
+            action.verb(),
+            totals.files,
+            format_size(totals.bytes)
+        );
+    } else if config.dry_run {
+        eprintln!(
+            "Would {} {} files, freeing {}",
+            action.verb(),
+            totals.files,
+            format_size(totals.bytes)
+        );
+    } else {
+        eprintln!(
+            "{} {} files, freeing {}",
+            action.past_tense(),
+            totals.files,
+            format_size(totals.bytes)
+        );
+    }
+}
+
+/// Copies of `group` to act on when those at positions `kept` are kept. Originals
+/// `in_reference` are kept whatever was chosen, and added to `kept`. `None` when no copy
+/// would be left in place: whatever chose the copies, at least one of them must be.
+fn removed_copies<'a>(
+    group: &'a DuplicateGroup,
+    kept: &mut Vec<usize>,
+    in_reference: impl Fn(&Path) -> bool,
+) -> Option<Vec<&'a FileEntry>> {
+    kept.extend(
+        (0..group.files.len()).filter(|&position| in_reference(&group.files[position].path)),
+    );
+    // Positions past the end of the group keep nothing.
+    kept.retain(|&position| position < group.files.len());
+    kept.sort_unstable();
+    kept.dedup();
+    let removed: Vec<&FileEntry> = group
+        .files
+        .iter()
+        .enumerate()
+        .filter(|(position, _)| !kept.contains(position))
+        .map(|(_, file)| file)
+        .collect();
+    (removed.len() < group.files.len()).then_some(removed)
+}
+
+/// Apply `action` to the `removed` copies of a group, as long as one of the `survivors`
+/// still holds the content. Files changed since they were hashed are left alone, and
+/// links point to the first survivor left unchanged. Moved files keep their path from
+/// the scan root they were found under. Files are only listed on a dry run, and the
+/// commands deleting them written with `--script`. With `--paranoid`, files are compared
+/// byte by byte with the survivor right before acting on them, and with
+/// `--merge-metadata` their metadata ends up on the survivor. What was done is recorded
+/// in the `journal`, if any.
+fn act_on_group(
+    config: &Config,
+    action: &Action,
+    hash: &FileHash,
+    survivors: &[&FileEntry],
+    removed: &[&FileEntry],
+    journal: &mut Option<Journal>,
+    totals: &mut Totals,
+) {
+    let verb = action.verb();
+    let Some(source) = survivors
+        .iter()
+        .find(|file| unchanged(file, config.follow_symlinks))
+    else {
+        log::error!(
+            "Not going to {verb} copies of `{}`, no kept copy is left as it was hashed",
+            survivors[0].path.to_string_lossy()
+        );
+        return;
+    };
+    if let Some(shell) = config.script {
+        report::emit(|out| writeln!(out, "{}", shell.keep_comment(&source.path)));
+    }
+    // The same file may be reached through several paths, such as symlinked directories.
+    if let Some((file, survivor)) = removed.iter().find_map(|file| {
+        survivors
+            .iter()
+            .find(|survivor| same_file(file, survivor))
+            .map(|survivor| (file, survivor))
+    }) {
+        log::error!(
+            "Refusing to {verb} `{}`, it is the kept `{}` under another path",
+            file.path.to_string_lossy(),
+            survivor.path.to_string_lossy()
+        );
+        return;
+    }
+    let mut merged: Option<MergedMetadata> = None;
+    for file in removed {
+        if !unchanged(file, config.follow_symlinks) {
+            log::warn!(
+                "Not going to {verb} `{}`, it changed since it was hashed",
+                file.path.to_string_lossy()
+            );
+            continue;
+        }
+        let other_device = matches!(
+            (file.file_id, source.file_id),
+            (Some((device, _)), Some((source_device, _))) if device != source_device
+        );
+        if other_device && matches!(action, Action::Link(LinkKind::Hard | LinkKind::Reflink)) {
+            log::warn!(
+                "Not going to {verb} `{}`, it is on another file system than `{}`",
+                file.path.to_string_lossy(),
+                source.path.to_string_lossy()
+            );
+            continue;
+        }
+        if let Some(shell) = config.script {
+            report::emit(|out| writeln!(out, "{}", shell.remove_command(&file.path)));
+            totals.files += 1;
+            totals.bytes += file.size;
+            continue;
+        }
+        let destination = match action {
+            Action::Move(quarantine) => {
+                Some(quarantine_path(quarantine, &file.path, &config.roots))
+            }
+            _ => None,
+        };
+        if config.dry_run {
+            let target = match action {
+                Action::Link(_) => Some(&source.path),
+                _ => destination.as_ref(),
+            };
+            match target {
+                Some(target) => eprintln!(
+                    "Would {verb} `{}` to `{}`",
+                    file.path.to_string_lossy(),
+                    target.to_string_lossy()
+                ),
+                None => eprintln!("Would {verb} `{}`", file.path.to_string_lossy()),
+            }
+            totals.files += 1;
+            totals.bytes += file.size;
+            continue;
+        }
+        // Rules out hash collisions and files rewritten with the same size and time.
+        if config.paranoid {
+            match files_equal(&source.path, &file.path, config.buffer_size) {
+                Ok(true) => {}
+                Ok(false) => {
+                    log::error!(
+                        "Not going to {verb} `{}`, its content differs from `{}`",
+                        file.path.to_string_lossy(),
+                        source.path.to_string_lossy()
+                    );
+                    continue;
+                }
+                Err(err) => {
+                    log::error!(
+                        "Error comparing files: `{}` and `{}` {err}",
+                        source.path.to_string_lossy(),
+                        file.path.to_string_lossy()
+                    );
+                    continue;
+                }
+            }
+        }
+        // Read before acting, the file may be gone or a link to the survivor after.
+        let metadata = config
+            .merge_metadata
+            .then(|| MergedMetadata::of(&file.path));
+        let result = match action {
+            Action::Delete => fs::remove_file(&file.path),
+            Action::Trash => trash::delete(&file.path).map_err(io::Error::other),
+            Action::Link(LinkKind::Hard) => {
+                replace_with(&file.path, |link| fs::hard_link(&source.path, link))
+            }
+            Action::Link(LinkKind::Reflink) => {
+                replace_with(&file.path, |link| platform::reflink(&source.path, link))
+            }
+            Action::Link(LinkKind::Soft(style)) => link_target(&source.path, &file.path, *style)
+                .and_then(|target| {
+                    replace_with(&file.path, |link| platform::symlink_file(&target, link))
+                }),
+            Action::Move(_) => move_file(
+                &file.path,
+                destination.as_deref().expect("Moves have a destination"),
+            ),
+        };
+        match result {
+            Ok(()) => {
+                log::info!("{} `{}`", action.past_tense(), file.path.to_string_lossy());
+                totals.files += 1;
+                totals.bytes += file.size;
+                if let Some(metadata) = metadata {
+                    merged.get_or_insert_default().merge(metadata);
+                }
+                let entry = Entry {
+                    action: verb,
+                    hash,
+                    survivor: &source.path,
+                    original: &file.path,
+                    destination: destination.as_deref(),
+                };
+                if let Some(Err(err)) = journal.as_mut().map(|journal| journal.record(&entry)) {
+                    log::error!(
+                        "Error recording `{}` in the journal: {err}",
+                        file.path.to_string_lossy()
+                    );
+                }
+            }
+            Err(err) => log::error!(
+                "Error trying to {verb} file: `{}` {err}",
+                file.path.to_string_lossy()
+            ),
+        }
+    }
+    if let Some(merged) = merged
+        && let Err(err) = merged.apply_to(&source.path)
+    {
+        log::error!(
+            "Error merging metadata onto `{}` {err}",
+            source.path.to_string_lossy()
+        );
+    }
+}
+
+/// How many temporary names `replace_with` tries before giving up.
+const TEMPORARY_ATTEMPTS: u32 = 16;
+
+/// Replace the file at `path` with what `create` makes at the path given to it. It is
+/// made next to `path` first, under a hidden name of its own, and renamed over it in a
+/// single step, so a crash never leaves `path` missing. Names left behind by a crashed
+/// run are skipped rather than reused.
+pub fn replace_with(
+    path: &Path,
+    mut create: impl FnMut(&Path) -> io::Result<()>,
+) -> io::Result<()> {
+    let name = path.file_name().unwrap_or_default();
+    for attempt in 0..TEMPORARY_ATTEMPTS {
+        let mut temporary = OsString::from(".");
+        temporary.push(name);
+        temporary.push(format!(".fdup-{}-{attempt}", std::process::id()));
+        let temporary = path.with_file_name(temporary);
+        match create(&temporary) {
+            Ok(()) => {}
+            // Whatever is already there is not ours to remove.
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => {
+                let _ = fs::remove_file(&temporary);
+                return Err(err);
+            }
+        }
+        return fs::rename(&temporary, path).inspect_err(|_| {
+            let _ = fs::remove_file(&temporary);
+        });
+    }
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        "no temporary name left free next to the file",
+    ))
+}
+
+/// Where the file at `path` is moved below `quarantine`: at its path from the one of
+/// `roots` it was found under. Only the normal components of that path are kept, so
+/// nothing ever lands outside of `quarantine`.
+fn quarantine_path(quarantine: &Path, path: &Path, roots: &[PathBuf]) -> PathBuf {
+    let relative = relative_to_root(path, roots)
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)));
+    quarantine.join(relative.collect::<PathBuf>())
+}
+
+/// Move the file at `from` to `to`, creating the directories it goes in. Nothing is
+/// overwritten, and files are copied then removed when going to another file system.
+pub fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::symlink_metadata(to).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("`{}` already exists", to.to_string_lossy()),
+        ));
+    }
+    match fs::rename(from, to) {
+        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+            if let Err(err) = fs::copy(from, to) {
+                let _ = fs::remove_file(to);
+                return Err(err);
+            }
+            fs::remove_file(from)
+        }
+        result => result,
+    }
+}
+
+/// What a symlink at `link` holds to point to the file at `target` in `style`. Relative
+/// targets fall back to absolute ones when there is no path between both, such as
+/// across Windows drives.
+fn link_target(target: &Path, link: &Path, style: LinkStyle) -> io::Result<PathBuf> {
+    let target = std::path::absolute(target)?;
+    if style == LinkStyle::Absolute {
+        return Ok(target);
+    }
+    let link = std::path::absolute(link)?;
+    let directory = link.parent().unwrap_or(&link);
+    let mut target_components = target.components().peekable();
+    let mut directory_components = directory.components().peekable();
+    if target_components.peek() != directory_components.peek() {
+        return Ok(target);
+    }
+    while target_components.peek().is_some()
+        && target_components.peek() == directory_components.peek()
+    {
+        target_components.next();
+        directory_components.next();
+    }
+    let mut relative: PathBuf = directory_components.map(|_| Component::ParentDir).collect();
+    relative.extend(target_components);
+    Ok(relative)
+}
+
+/// Whether `first` and `second` are the same file on disk, whatever their paths.
+fn same_file(first: &FileEntry, second: &FileEntry) -> bool {
+    if let (Some(first), Some(second)) = (first.file_id, second.file_id) {
+        return first == second;
+    }
+    match (
+        fs::canonicalize(&first.path),
+        fs::canonicalize(&second.path),
+    ) {
+        (Ok(first), Ok(second)) => first == second,
+        _ => first.path == second.path,
+    }
+}
+
+/// Whether `file` is still there with the size and modification time it was hashed with.
+/// Files reached through symlinks are described by their target when `follow_symlinks`
+/// is set, as they were while walking. Otherwise a symlink is never the file hashed.
+fn unchanged(file: &FileEntry, follow_symlinks: bool) -> bool {
+    let meta = match follow_symlinks {
+        true => fs::metadata(&file.path),
+        false => fs::symlink_metadata(&file.path),
+    };
+    match meta {
+        Ok(meta) => {
+            meta.is_file()
+                && meta.len() == file.size
+                && (file.modified.is_none() || meta.modified().ok() == file.modified)
+        }
+        Err(_) => false,
+    }
+}
+
+/// Answer to which copies of a group to keep.
+#[derive(Debug, PartialEq, Eq)]
+enum Choice {
+    /// Indexes of the files kept.
+    Keep(Vec<usize>),
+    /// Keep every copy and move on to the next group.
+    Skip,
+    /// Stop acting, what was already done is still reported.
+    Quit,
+}
+
+/// Answer to whether to act on the copies not kept.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Confirmation {
+    Yes,
+    No,
+    /// Yes, and keep the same copies of every remaining group without asking.
+    All,
+    Quit,
+}
+
+/// List the files of `group` and ask which of them to keep, by number, until a valid
+/// answer is given. Quits when stdin ends.
+fn ask_kept(
+    input: &mut impl BufRead,
+    group: &DuplicateGroup,
+    index: usize,
+    count: usize,
+) -> io::Result<Choice> {
+    let mut prompt = io::stderr().lock();
+    writeln!(prompt)?;
+    for (position, file) in group.files.iter().enumerate() {
+        writeln!(prompt, "[{}] {}", position + 1, describe(file))?;
+    }
+    loop {
+        write!(
+            prompt,
+            "\nSet {} of {count}, preserve files [1 - {}, skip, quit]: ",
+            index + 1,
+            group.files.len()
+        )?;
+        prompt.flush()?;
+        let mut answer = String::new();
+        if input.read_line(&mut answer)? == 0 {
+            return Ok(Choice::Quit);
+        }
+        match parse_kept(answer.trim(), group.files.len()) {
+            Ok(choice) => return Ok(choice),
+            Err(err) => writeln!(prompt, "{err}")?,
+        }
+    }
+}
+
+/// The choice made by `answer`: numbers of the files kept separated by spaces or
+/// commas, ranges such as `2-4`, `skip` to keep them all, or `quit`.
+fn parse_kept(answer: &str, files: usize) -> Result<Choice, String> {
+    match answer.to_ascii_lowercase().as_str() {
+        "skip" | "s" => return Ok(Choice::Skip),
+        "quit" | "q" => return Ok(Choice::Quit),
+        "" => return Err("Choose at least one file to keep".to_string()),
+        _ => {}
+    }
+    let mut kept = Vec::new();
+    for choice in answer.split([' ', ',']).filter(|choice| !choice.is_empty()) {
+        let number = |text: &str| match text.trim().parse::<usize>() {
+            Ok(number) if (1..=files).contains(&number) => Ok(number - 1),
+            _ => Err(format!(
+                "Invalid choice `{choice}`, expected numbers from 1 to {files}"
+            )),
+        };
+        let (first, last) = match choice.split_once('-') {
+            Some((first, last)) => (number(first)?, number(last)?),
+            None => (number(choice)?, number(choice)?),
+        };
+        kept.extend(first.min(last)..=first.max(last));
+    }
+    if kept.is_empty() {
+        return Err("Choose at least one file to keep".to_string());
+    }
+    kept.sort_unstable();
+    kept.dedup();
+    Ok(Choice::Keep(kept))
+}
+
+/// Ask before `action` is applied to `count` files, nothing happens unless told yes.
+/// `all` says yes for every remaining group as well.
+fn confirm(input: &mut impl BufRead, action: &Action, count: usize) -> io::Result<Confirmation> {
+    let question = match action {
+        Action::Delete => "Delete",
+        Action::Trash => "Trash",
+        Action::Link(LinkKind::Hard) => "Hardlink",
+        Action::Link(LinkKind::Soft(_)) => "Symlink",
+        Action::Link(LinkKind::Reflink) => "Reflink",
+        Action::Move(_) => "Move",
+    };
+    let mut prompt = io::stderr().lock();
+    write!(
+        prompt,
+        "{question} {count} files? [y/N, all keeps the same copies from now on, quit]: "
+    )?;
+    prompt.flush()?;
+    let mut answer = String::new();
+    if input.read_line(&mut answer)? == 0 {
+        return Ok(Confirmation::Quit);
+    }
+    Ok(match answer.trim().to_ascii_lowercase().as_str() {
+        "y" | "yes" => Confirmation::Yes,
+        "all" | "a" => Confirmation::All,
+        "quit" | "q" => Confirmation::Quit,
+        _ => Confirmation::No,
+    })
+}
+
+/// The path of `file` along with when it was modified, to help choosing which copy to keep.
+fn describe(file: &FileEntry) -> String {
+    let path = file.path.to_string_lossy();
+    match file.modified {
+        Some(modified) => format!("{path}  (modified {})", format_time(modified)),
+        None => path.into_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::group_of;
+
+    #[test]
+    fn keeping_no_copy_is_refused() {
+        let group = group_of(&["a", "b", "c"]);
+        assert!(removed_copies(&group, &mut Vec::new(), |_| false).is_none());
+    }
+
+    #[test]
+    fn keeping_missing_copies_is_refused() {
+        let group = group_of(&["a", "b"]);
+        let mut kept = vec![2, 7];
+        assert!(removed_copies(&group, &mut kept, |_| false).is_none());
+        assert!(kept.is_empty());
+    }
+
+    #[test]
+    fn chosen_copies_are_kept() {
+        let group = group_of(&["a", "b", "c"]);
+        let mut kept = vec![1];
+        let removed = removed_copies(&group, &mut kept, |_| false).unwrap();
+        let removed: Vec<&Path> = removed.iter().map(|file| file.path.as_path()).collect();
+        assert_eq!(removed, [Path::new("a"), Path::new("c")]);
+        assert_eq!(kept, [1]);
+    }
+
+    #[test]
+    fn reference_copies_are_always_kept() {
+        let group = group_of(&["reference/a", "b", "c"]);
+        let mut kept = Vec::new();
+        let removed =
+            removed_copies(&group, &mut kept, |path| path.starts_with("reference")).unwrap();
+        assert_eq!(removed.len(), 2);
+        assert_eq!(kept, [0]);
+    }
+
+    #[test]
+    fn empty_answer_is_refused() {
+        assert!(parse_kept("", 2).is_err());
+        assert!(parse_kept(" , ", 2).is_err());
+    }
+
+    #[test]
+    fn answers_out_of_range_are_refused() {
+        assert!(parse_kept("3", 2).is_err());
+        assert!(parse_kept("0", 2).is_err());
+        assert!(matches!(parse_kept("1, 2", 2), Ok(Choice::Keep(kept)) if kept == [0, 1]));
+    }
+}
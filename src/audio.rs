@@ -0,0 +1,238 @@
+use std::{fs::File, io, path::Path, str::FromStr};
+
+use rusty_chromaprint::{Configuration, Fingerprinter};
+use symphonia::core::{
+    audio::SampleBuffer,
+    codecs::DecoderOptions,
+    errors::Error as DecodeError,
+    formats::{FormatOptions, FormatReader, Packet, Track},
+    io::MediaSourceStream,
+    meta::MetadataOptions,
+    probe::Hint,
+};
+
+use crate::{
+    DuplicateGroup,
+    config::Config,
+    hash::{FileHash, HashAlgorithm, Hasher, get_parts_hash},
+    magic::{self, FileKind},
+    media, open_files,
+    progress::{PROGRESS, Phase},
+    walk::FileEntry,
+};
+
+/// Seconds from the start of every track fingerprinted, enough to tell songs apart.
+const FINGERPRINT_SECONDS: u64 = 120;
+
+/// Share of the bits of two fingerprints that may differ for their tracks to be deemed
+/// the same song. Different encodings of a recording stay well below it.
+const MAX_BIT_ERRORS: f64 = 0.15;
+
+/// Fingerprint items two tracks may be shifted by, covering the silence encoders add.
+const MAX_SHIFT: usize = 3;
+
+/// How audio files are compared by `--audio`, instead of by their bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AudioMode {
+    /// Grouped when they sound the same, whatever their format or bitrate.
+    Similar,
+    /// Grouped when their audio frames are the same, whatever tags their files hold.
+    Frames,
+}
+
+impl FromStr for AudioMode {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "similar" => Ok(AudioMode::Similar),
+            "frames" => Ok(AudioMode::Frames),
+            _ => Err(format!(
+                "Unknown audio mode `{name}`, expected one of: similar, frames"
+            )),
+        }
+    }
+}
+
+/// Acoustic fingerprint of the first `FINGERPRINT_SECONDS` of the track at `path`, in
+/// the manner of Chromaprint: one item per eighth of a second or so, describing how
+/// the energy moves across frequencies.
+fn fingerprint(path: &Path) -> io::Result<Vec<u32>> {
+    let _permit = open_files::acquire(1);
+    let (mut format, track) = open(path)?;
+    let (Some(sample_rate), Some(channels)) =
+        (track.codec_params.sample_rate, track.codec_params.channels)
+    else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unknown sample rate or channels",
+        ));
+    };
+    let channels = channels.count() as u32;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .map_err(invalid)?;
+
+    let configuration = Configuration::preset_test2();
+    let mut printer = Fingerprinter::new(&configuration);
+    printer
+        .start(sample_rate, channels)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{err:?}")))?;
+    let mut samples_left = u64::from(sample_rate) * u64::from(channels) * FINGERPRINT_SECONDS;
+    while samples_left > 0 {
+        let Some(packet) = next_packet(format.as_mut(), track.id)? else {
+            break;
+        };
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // A damaged packet, the next ones may still decode.
+            Err(DecodeError::DecodeError(_)) => continue,
+            Err(err) => return Err(invalid(err)),
+        };
+        let mut samples = SampleBuffer::<i16>::new(decoded.capacity() as u64, *decoded.spec());
+        samples.copy_interleaved_ref(decoded);
+        let samples = samples.samples();
+        let taken = samples
+            .len()
+            .min(samples_left.try_into().unwrap_or(usize::MAX));
+        printer.consume(&samples[..taken]);
+        samples_left -= taken as u64;
+    }
+    printer.finish();
+    Ok(printer.fingerprint().to_vec())
+}
+
+/// Hash of the audio frames of the track at `path`, as stored, leaving out the tags its
+/// file holds: ID3 in MP3 files, Vorbis comments in FLAC files or the metadata atoms of
+/// M4A files. `None` for files that are not audio.
+pub fn frame_hash(path: &Path, algorithm: HashAlgorithm) -> io::Result<Option<FileHash>> {
+    if magic::sniff(path)? != Some(FileKind::Audio) {
+        return Ok(None);
+    }
+    let _permit = open_files::acquire(1);
+    let (mut format, track) = open(path)?;
+    let mut hasher = Hasher::new(algorithm);
+    while let Some(packet) = next_packet(format.as_mut(), track.id)? {
+        hasher.update(&packet.data);
+    }
+    Ok(Some(hasher.finish()))
+}
+
+/// The track of the audio file at `path` played by default, along with a reader of
+/// its packets.
+fn open(path: &Path) -> io::Result<(Box<dyn FormatReader>, Track)> {
+    let stream = MediaSourceStream::new(Box::new(File::open(path)?), Default::default());
+    let format = symphonia::default::get_probe()
+        .format(
+            &Hint::new(),
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .map_err(invalid)?
+        .format;
+    let track = format
+        .default_track()
+        .cloned()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no audio track"))?;
+    Ok((format, track))
+}
+
+/// The next packet of track `track_id` read by `format`, `None` at the end of the file.
+fn next_packet(format: &mut dyn FormatReader, track_id: u32) -> io::Result<Option<Packet>> {
+    loop {
+        match format.next_packet() {
+            Ok(packet) if packet.track_id() == track_id => return Ok(Some(packet)),
+            Ok(_) => {}
+            Err(DecodeError::IoError(err)) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                return Ok(None);
+            }
+            Err(err) => return Err(invalid(err)),
+        }
+    }
+}
+
+/// `err` told as a file holding invalid data.
+fn invalid(err: DecodeError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// Whether fingerprints `a` and `b` come from the same recording: aligned on their
+/// best shift, few of the bits they overlap on differ.
+fn same_recording(a: &[u32], b: &[u32]) -> bool {
+    let differing = |a: &[u32], b: &[u32]| -> Option<f64> {
+        let overlap = a.len().min(b.len());
+        if overlap == 0 {
+            return None;
+        }
+        let errors: u32 = a.iter().zip(b).map(|(a, b)| (a ^ b).count_ones()).sum();
+        Some(f64::from(errors) / (overlap as f64 * 32.0))
+    };
+    (0..=MAX_SHIFT)
+        .flat_map(|shift| {
+            [
+                differing(a.get(shift..).unwrap_or_default(), b),
+                differing(a, b.get(shift..).unwrap_or_default()),
+            ]
+        })
+        .flatten()
+        .any(|errors| errors <= MAX_BIT_ERRORS)
+}
+
+/// Groups of audio files among `files` holding the same recording, told by their
+/// acoustic fingerprints: the same song in different formats or bitrates. Tracks of
+/// clearly different lengths are never compared. Files that are not audio are left out.
+pub fn find_similar(files: Vec<FileEntry>, config: &Config) -> Vec<DuplicateGroup> {
+    PROGRESS.start_phase(Phase::Hashing, files.iter().map(|file| file.size).sum());
+    let mut prints = media::key_each(&files, config.threads, |file| {
+        match magic::sniff(&file.path)? {
+            Some(FileKind::Audio) => fingerprint(&file.path).map(Some),
+            _ => Ok(None),
+        }
+    });
+    prints.retain(|(_, print)| !print.is_empty());
+    // Fingerprints as long as each other sit next to each other, only those are compared.
+    let mut order: Vec<usize> = (0..prints.len()).collect();
+    order.sort_by_key(|&print| prints[print].1.len());
+    let mut parents: Vec<usize> = (0..prints.len()).collect();
+    for (position, &print) in order.iter().enumerate() {
+        let length = prints[print].1.len();
+        for &other in &order[position + 1..] {
+            // Fingerprints stop growing past `FINGERPRINT_SECONDS`, the longest tracks
+            // all compare with each other.
+            if prints[other].1.len() > length + length / 10 + MAX_SHIFT {
+                break;
+            }
+            if same_recording(&prints[print].1, &prints[other].1) {
+                let (first, second) = (
+                    media::find_set(&mut parents, print),
+                    media::find_set(&mut parents, other),
+                );
+                parents[first.max(second)] = first.min(second);
+            }
+        }
+    }
+    let mut members: Vec<Vec<usize>> = vec![Vec::new(); prints.len()];
+    for print in 0..prints.len() {
+        let root = media::find_set(&mut parents, print);
+        members[root].push(print);
+    }
+    members
+        .into_iter()
+        .filter(|prints| prints.len() > 1)
+        .map(|members| {
+            let bytes: Vec<u8> = prints[members[0]]
+                .1
+                .iter()
+                .flat_map(|item| item.to_le_bytes())
+                .collect();
+            DuplicateGroup {
+                hash: get_parts_hash(&[&bytes], config.algorithm),
+                files: members
+                    .iter()
+                    .map(|&print| files[prints[print].0].clone())
+                    .collect(),
+            }
+        })
+        .collect()
+}
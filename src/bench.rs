@@ -0,0 +1,151 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    hash::{BufferSize, HashAlgorithm, ReadOptions, get_file_hash},
+    units::format_size,
+};
+
+/// Files hashed by default, spread evenly over the tree.
+const DEFAULT_SAMPLE: usize = 64;
+
+/// Files considered at most when picking the sample, so huge trees are not walked whole.
+const MAX_CANDIDATES: usize = 10_000;
+
+/// Buffer sizes compared for every algorithm.
+const BUFFER_SIZES: [BufferSize; 4] = [
+    BufferSize::Fixed(4 * 1024),
+    BufferSize::Fixed(64 * 1024),
+    BufferSize::Fixed(1024 * 1024),
+    BufferSize::Adaptive,
+];
+
+const USAGE: &str = "Usage: fdup bench <path> [--files <count>]";
+
+/// Run `fdup bench`: hash a sample of the files under a path with every algorithm and
+/// buffer size, and print how fast each combination went.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut path = None;
+    let mut sample_size = DEFAULT_SAMPLE;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--files" => {
+                sample_size = args
+                    .next()
+                    .and_then(|count| count.parse().ok())
+                    .filter(|&count| count > 0)
+                    .ok_or(USAGE)?;
+            }
+            _ if path.is_none() && !arg.starts_with('-') => path = Some(PathBuf::from(arg)),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let path = path.ok_or(USAGE)?;
+
+    let sample = pick_sample(&path, sample_size);
+    if sample.is_empty() {
+        return Err(format!(
+            "No files to benchmark under `{}`",
+            path.to_string_lossy()
+        ));
+    }
+    let total_bytes: u64 = sample.iter().map(|(_, size)| size).sum();
+    // Read everything once, so the first combination does not pay for a cold cache alone.
+    for (file, _) in &sample {
+        let _ = fs::read(file);
+    }
+
+    println!(
+        "Benchmarked {} files, {} in total",
+        sample.len(),
+        format_size(total_bytes)
+    );
+    print!("{:<10}", "algorithm");
+    for buffer_size in BUFFER_SIZES {
+        print!("{:>14}", buffer_label(buffer_size));
+    }
+    println!();
+
+    let mut fastest: Option<(HashAlgorithm, BufferSize, Duration)> = None;
+    for algorithm in HashAlgorithm::ALL {
+        print!("{:<10}", algorithm.name());
+        for buffer_size in BUFFER_SIZES {
+            let options = ReadOptions {
+                use_mmap: false,
+                buffer_size,
+                parallel: true,
+                io_uring: false,
+            };
+            let start = Instant::now();
+            for (file, _) in &sample {
+                let _ = get_file_hash(file, algorithm, options);
+            }
+            let elapsed = start.elapsed();
+            print!("{:>14}", throughput(total_bytes, elapsed));
+            if fastest.is_none_or(|(_, _, best)| elapsed < best) {
+                fastest = Some((algorithm, buffer_size, elapsed));
+            }
+        }
+        println!();
+    }
+
+    if let Some((algorithm, buffer_size, elapsed)) = fastest {
+        println!(
+            "Fastest: {} with {} buffers ({})",
+            algorithm.name(),
+            buffer_label(buffer_size),
+            throughput(total_bytes, elapsed)
+        );
+    }
+    Ok(())
+}
+
+/// Up to `count` files under `root`, spread evenly over the tree, with their sizes.
+fn pick_sample(root: &Path, count: usize) -> Vec<(PathBuf, u64)> {
+    let mut candidates = Vec::new();
+    let mut directories = vec![root.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        let Ok(entries) = fs::read_dir(&directory) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            // Symlinks are skipped, they could loop or leave the tree.
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                directories.push(entry.path());
+            } else if file_type.is_file()
+                && let Ok(meta) = entry.metadata()
+            {
+                candidates.push((entry.path(), meta.len()));
+                if candidates.len() >= MAX_CANDIDATES {
+                    directories.clear();
+                    break;
+                }
+            }
+        }
+    }
+
+    candidates.sort();
+    let step = candidates.len().div_ceil(count).max(1);
+    candidates.into_iter().step_by(step).collect()
+}
+
+fn buffer_label(buffer_size: BufferSize) -> String {
+    match buffer_size {
+        BufferSize::Fixed(size) => format_size(size as u64),
+        BufferSize::Adaptive => "auto".to_string(),
+    }
+}
+
+fn throughput(bytes: u64, elapsed: Duration) -> String {
+    match elapsed.as_secs_f64() {
+        0.0 => "-".to_string(),
+        seconds => format!("{}/s", format_size((bytes as f64 / seconds) as u64)),
+    }
+}
@@ -0,0 +1,185 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::hash::{FileHash, HashAlgorithm};
+
+/// Digest of a file as it was when last hashed.
+struct CacheEntry {
+    size: u64,
+    modified: u128,
+    digest: String,
+}
+
+/// On-disk cache of full file hashes.
+/// Entries are keyed by path and algorithm, and only reused while the
+/// file keeps the size and modification time it had when it was hashed.
+pub struct HashCache {
+    location: PathBuf,
+    entries: HashMap<(PathBuf, HashAlgorithm), CacheEntry>,
+    changed: bool,
+}
+
+impl HashCache {
+    /// Load the cache stored at `location`.
+    /// A missing or unreadable cache simply starts out empty.
+    pub fn load(location: PathBuf) -> HashCache {
+        let mut entries = HashMap::new();
+        if let Ok(file) = fs::File::open(&location) {
+            for line in BufReader::new(file).lines().map_while(Result::ok) {
+                if let Some((key, entry)) = parse_line(&line) {
+                    entries.insert(key, entry);
+                }
+            }
+        }
+        HashCache {
+            location,
+            entries,
+            changed: false,
+        }
+    }
+
+    /// Find the hash stored for `path`, as long as the file did not change since.
+    pub fn get(
+        &self,
+        path: &Path,
+        size: u64,
+        modified: Option<SystemTime>,
+        algorithm: HashAlgorithm,
+    ) -> Option<FileHash> {
+        let modified = to_nanos(modified?)?;
+        let entry = self.entries.get(&(path.to_path_buf(), algorithm))?;
+        (entry.size == size && entry.modified == modified).then(|| FileHash {
+            algorithm,
+            digest: entry.digest.clone(),
+        })
+    }
+
+    /// Remember the hash computed for `path`.
+    /// Files without a usable modification time are never cached, a change could not be noticed.
+    pub fn insert(
+        &mut self,
+        path: PathBuf,
+        size: u64,
+        modified: Option<SystemTime>,
+        hash: &FileHash,
+    ) {
+        let Some(modified) = modified.and_then(to_nanos) else {
+            return;
+        };
+        self.entries.insert(
+            (path, hash.algorithm),
+            CacheEntry {
+                size,
+                modified,
+                digest: hash.digest.clone(),
+            },
+        );
+        self.changed = true;
+    }
+
+    /// Drop every entry whose path does not satisfy `keep`.
+    pub fn retain(&mut self, mut keep: impl FnMut(&Path) -> bool) {
+        let count = self.entries.len();
+        self.entries.retain(|(path, _), _| keep(path));
+        self.changed |= self.entries.len() != count;
+    }
+
+    /// Write the cache back to disk when anything was added.
+    /// The cache is written to a temporary file first, so an interrupted write never
+    /// corrupts the previous one.
+    pub fn save(&self) -> io::Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+        if let Some(parent) = self.location.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temporary = self.location.with_extension("tmp");
+        let mut writer = BufWriter::new(fs::File::create(&temporary)?);
+        for ((path, algorithm), entry) in &self.entries {
+            // Paths must fit on a single line of valid text to be read back.
+            let Some(path) = path.to_str().filter(|path| !path.contains('\n')) else {
+                continue;
+            };
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}",
+                entry.size, entry.modified, algorithm, entry.digest, path
+            )?;
+        }
+        writer.flush()?;
+        drop(writer);
+        fs::rename(&temporary, &self.location)
+    }
+}
+
+/// Parse one `size, mtime, algorithm, digest, path` line of the cache file.
+fn parse_line(line: &str) -> Option<((PathBuf, HashAlgorithm), CacheEntry)> {
+    let mut fields = line.splitn(5, '\t');
+    let size = fields.next()?.parse().ok()?;
+    let modified = fields.next()?.parse().ok()?;
+    let algorithm = fields.next()?.parse().ok()?;
+    let digest = fields.next()?.to_string();
+    let path = PathBuf::from(fields.next()?);
+    Some((
+        (path, algorithm),
+        CacheEntry {
+            size,
+            modified,
+            digest,
+        },
+    ))
+}
+
+/// Modification time as nanoseconds since the Unix epoch, `None` for earlier times.
+pub fn to_nanos(time: SystemTime) -> Option<u128> {
+    time.duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|elapsed| elapsed.as_nanos())
+}
+
+/// Inverse of `to_nanos`.
+pub fn from_nanos(nanos: u128) -> SystemTime {
+    let seconds = (nanos / 1_000_000_000) as u64;
+    UNIX_EPOCH + Duration::new(seconds, (nanos % 1_000_000_000) as u32)
+}
+
+/// Default location of the hash cache, inside the platform cache directory.
+pub fn default_cache_path() -> Option<PathBuf> {
+    cache_dir().map(|dir| dir.join("fdup").join("hashes.tsv"))
+}
+
+/// Default location of the journal of files acted upon, read back by `fdup undo`.
+pub fn default_journal_path() -> Option<PathBuf> {
+    cache_dir().map(|dir| dir.join("fdup").join("journal.tsv"))
+}
+
+/// Location of the state kept by incremental scans of `root`.
+/// Each scanned path gets its own file, named after a hash of its canonical form.
+pub fn scan_state_path(root: &Path) -> Option<PathBuf> {
+    let root = fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+    let name = format!("{:016x}.tsv", xxh3_64(root.to_string_lossy().as_bytes()));
+    cache_dir().map(|dir| dir.join("fdup").join("scans").join(name))
+}
+
+/// Platform cache directory: `%LOCALAPPDATA%` on Windows, `~/Library/Caches` on macOS
+/// and `$XDG_CACHE_HOME` (or `~/.cache`) everywhere else.
+fn cache_dir() -> Option<PathBuf> {
+    if cfg!(target_os = "windows") {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join("Library").join("Caches"))
+    } else {
+        env::var_os("XDG_CACHE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+    }
+}
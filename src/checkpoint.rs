@@ -0,0 +1,275 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{
+    cache::{from_nanos, to_nanos},
+    hash::FileHash,
+    log,
+    walk::FileEntry,
+};
+
+/// How often a running scan writes its progress to the checkpoint file.
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// First line of every checkpoint file.
+const HEADER: &str = "fdup checkpoint 1";
+
+/// Full hash recorded for a file, along with the metadata it was computed for.
+struct HashRecord {
+    size: u64,
+    modified: u128,
+    hash: FileHash,
+}
+
+/// Walk state of an earlier run, read back from its checkpoint file.
+pub struct Resumed {
+    /// Directories that were not walked yet, empty when the walk had completed.
+    pub pending: Vec<PathBuf>,
+    pub files: Vec<FileEntry>,
+}
+
+/// Periodically saved state of a running scan.
+/// While walking, the whole state is rewritten to a temporary file that replaces the
+/// previous checkpoint. Once the walk is done, full hashes are only appended to it.
+pub struct Checkpoint {
+    location: PathBuf,
+    root: PathBuf,
+    last_saved: Mutex<Instant>,
+    /// Hashes found by the run being resumed, written out again with the walk results.
+    resumed_hashes: HashMap<PathBuf, HashRecord>,
+    log: Mutex<Option<BufWriter<fs::File>>>,
+}
+
+impl Checkpoint {
+    /// Start checkpointing a new scan of `root` to `location`.
+    pub fn new(location: PathBuf, root: PathBuf) -> Checkpoint {
+        Checkpoint {
+            location,
+            root,
+            last_saved: Mutex::new(Instant::now()),
+            resumed_hashes: HashMap::new(),
+            log: Mutex::new(None),
+        }
+    }
+
+    /// Pick up the scan of `root` saved at `location`, returning where its walk stopped.
+    /// The checkpoint keeps being updated as the scan goes on.
+    pub fn resume(location: PathBuf, root: PathBuf) -> Result<(Checkpoint, Resumed), String> {
+        let file = fs::File::open(&location).map_err(|err| {
+            format!(
+                "Error reading checkpoint: `{}` {err}",
+                location.to_string_lossy()
+            )
+        })?;
+        let mut lines = BufReader::new(file).lines().map_while(Result::ok);
+        if lines.next().as_deref() != Some(HEADER) {
+            return Err(format!(
+                "`{}` is not a checkpoint file",
+                location.to_string_lossy()
+            ));
+        }
+
+        let mut checkpoint = Checkpoint::new(location, root);
+        let mut resumed = Resumed {
+            pending: Vec::new(),
+            files: Vec::new(),
+        };
+        for line in lines {
+            // A crash may leave a truncated last line behind, it is simply skipped.
+            let Some((kind, fields)) = line.split_once('\t') else {
+                continue;
+            };
+            match kind {
+                "root" if Path::new(fields) != checkpoint.root => {
+                    return Err(format!(
+                        "Checkpoint `{}` belongs to a scan of `{fields}`, not `{}`",
+                        checkpoint.location.to_string_lossy(),
+                        checkpoint.root.to_string_lossy()
+                    ));
+                }
+                "pending" => resumed.pending.push(PathBuf::from(fields)),
+                "file" => resumed.files.extend(parse_file(fields)),
+                "hash" => {
+                    if let Some((path, record)) = parse_hash(fields) {
+                        checkpoint.resumed_hashes.insert(path, record);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok((checkpoint, resumed))
+    }
+
+    /// Hash found for `file` by the resumed run, as long as the file did not change since.
+    pub fn known_hash(&self, file: &FileEntry) -> Option<FileHash> {
+        let record = self.resumed_hashes.get(&file.path)?;
+        let modified = to_nanos(file.modified?)?;
+        (record.size == file.size && record.modified == modified).then(|| record.hash.clone())
+    }
+
+    /// Whether it is time to save again. Only the first caller to ask gets `true`,
+    /// so the checkpoint is written by a single thread at a time.
+    pub fn due(&self) -> bool {
+        let mut last_saved = self
+            .last_saved
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if last_saved.elapsed() < CHECKPOINT_INTERVAL {
+            return false;
+        }
+        *last_saved = Instant::now();
+        true
+    }
+
+    /// Replace the checkpoint with the walk state: directories still `pending` and the
+    /// `files` found so far. Once the walk is complete the file stays open, ready for
+    /// `record_hash` to append to it.
+    pub fn save_walk(&self, pending: &[PathBuf], files: &[FileEntry]) {
+        if let Err(err) = self.write_walk(pending, files) {
+            log::error!("Error saving checkpoint: {err}");
+        }
+    }
+
+    fn write_walk(&self, pending: &[PathBuf], files: &[FileEntry]) -> io::Result<()> {
+        if let Some(parent) = self.location.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temporary = self.location.with_extension("tmp");
+        let mut writer = BufWriter::new(fs::File::create(&temporary)?);
+        writeln!(writer, "{HEADER}")?;
+        if let Some(root) = printable(&self.root) {
+            writeln!(writer, "root\t{root}")?;
+        }
+        for directory in pending.iter().filter_map(|path| printable(path)) {
+            writeln!(writer, "pending\t{directory}")?;
+        }
+        for file in files {
+            let Some(path) = printable(&file.path) else {
+                continue;
+            };
+            let modified = file.modified.and_then(to_nanos);
+            let modified = modified.map_or("-".to_string(), |nanos| nanos.to_string());
+            let file_id = file.file_id.map_or("-".to_string(), |(device, inode)| {
+                format!("{device}:{inode}")
+            });
+            writeln!(writer, "file\t{}\t{modified}\t{file_id}\t{path}", file.size)?;
+        }
+        for (path, record) in &self.resumed_hashes {
+            write_hash(&mut writer, path, record)?;
+        }
+        writer.flush()?;
+        drop(writer);
+        fs::rename(&temporary, &self.location)?;
+
+        if pending.is_empty() {
+            let file = fs::OpenOptions::new().append(true).open(&self.location)?;
+            *self.log.lock().unwrap_or_else(PoisonError::into_inner) = Some(BufWriter::new(file));
+        }
+        Ok(())
+    }
+
+    /// Append the full hash of `file` to the checkpoint, after the walk is complete.
+    pub fn record_hash(&self, file: &FileEntry, hash: &FileHash) {
+        let Some(modified) = file.modified.and_then(to_nanos) else {
+            return;
+        };
+        let record = HashRecord {
+            size: file.size,
+            modified,
+            hash: hash.clone(),
+        };
+        let mut log = self.log.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(writer) = log.as_mut() {
+            let mut result = write_hash(writer, &file.path, &record);
+            if result.is_ok() && self.due() {
+                result = writer.flush();
+            }
+            if let Err(err) = result {
+                log::error!("Error saving checkpoint: {err}");
+                *log = None;
+            }
+        }
+    }
+
+    /// Make sure every recorded hash reached the disk.
+    pub fn flush(&self) {
+        let mut log = self.log.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(Err(err)) = log.as_mut().map(Write::flush) {
+            log::error!("Error saving checkpoint: {err}");
+        }
+    }
+
+    /// Delete the checkpoint once the scan it covers completed.
+    pub fn remove(self) {
+        drop(self.log);
+        if let Err(err) = fs::remove_file(&self.location)
+            && err.kind() != io::ErrorKind::NotFound
+        {
+            log::error!("Error removing checkpoint: {err}");
+        }
+    }
+}
+
+/// Paths must fit on a single line of valid text to be read back.
+fn printable(path: &Path) -> Option<&str> {
+    path.to_str().filter(|path| !path.contains('\n'))
+}
+
+fn write_hash(writer: &mut impl Write, path: &Path, record: &HashRecord) -> io::Result<()> {
+    let Some(path) = printable(path) else {
+        return Ok(());
+    };
+    writeln!(
+        writer,
+        "hash\t{}\t{}\t{}\t{}\t{path}",
+        record.size, record.modified, record.hash.algorithm, record.hash.digest
+    )
+}
+
+/// Parse the `size, mtime, file id, path` fields of a `file` line.
+fn parse_file(fields: &str) -> Option<FileEntry> {
+    let mut fields = fields.splitn(4, '\t');
+    let size = fields.next()?.parse().ok()?;
+    let modified: Option<SystemTime> = match fields.next()? {
+        "-" => None,
+        nanos => Some(from_nanos(nanos.parse().ok()?)),
+    };
+    let file_id = match fields.next()? {
+        "-" => None,
+        id => {
+            let (device, inode) = id.split_once(':')?;
+            Some((device.parse().ok()?, inode.parse().ok()?))
+        }
+    };
+    Some(FileEntry {
+        path: PathBuf::from(fields.next()?),
+        size,
+        modified,
+        file_id,
+        known_hash: None,
+    })
+}
+
+/// Parse the `size, mtime, algorithm, digest, path` fields of a `hash` line.
+fn parse_hash(fields: &str) -> Option<(PathBuf, HashRecord)> {
+    let mut fields = fields.splitn(5, '\t');
+    let size = fields.next()?.parse().ok()?;
+    let modified = fields.next()?.parse().ok()?;
+    let algorithm = fields.next()?.parse().ok()?;
+    let digest = fields.next()?.to_string();
+    let path = PathBuf::from(fields.next()?);
+    Some((
+        path,
+        HashRecord {
+            size,
+            modified,
+            hash: FileHash { algorithm, digest },
+        },
+    ))
+}
@@ -0,0 +1,914 @@
+use std::{
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use clarg::ArgMap;
+
+use crate::{
+    actions::{Action, KeepPolicy, LinkKind, Protected, Selection, Shell},
+    audio::AudioMode,
+    cache,
+    devices::DeviceProfile,
+    exec::{ExecCommand, ExecScope},
+    filter::{Filters, parse_excluded_dirs, parse_extensions, parse_glob, parse_regex},
+    hash::{BufferSize, HashAlgorithm, ReadOptions},
+    images::{self, ImageMode},
+    log::Level,
+    magic::FileKind,
+    platform,
+    report::{ColorChoice, GroupOrder, OutputFormat, Template},
+    text::{self, TextMode},
+    units::{parse_duration, parse_size, parse_time},
+    video::VideoMode,
+};
+
+/// Files kept open at once when the process limit is unknown.
+const DEFAULT_MAX_OPEN_FILES: usize = 1024;
+
+/// Options controlling a scan, parsed once from the command line.
+pub struct Config {
+    /// The first path being scanned, which checkpoints and incremental scans go by.
+    pub path: PathBuf,
+    /// Every path being scanned.
+    pub roots: Vec<PathBuf>,
+    /// List of the files compared instead of walking the paths, `-` for stdin.
+    pub files_from: Option<PathBuf>,
+    /// Directory holding the originals: only copies of its files are reported, and none
+    /// of its files is ever acted upon. Also one of the roots.
+    pub reference: Option<PathBuf>,
+    pub recurse: bool,
+    /// How many levels of directories below the path are walked, all of them when `None`.
+    pub max_depth: Option<usize>,
+    pub include_hidden: bool,
+    /// Never walk into directories on another file system than their parent.
+    pub one_file_system: bool,
+    /// Walk into symlinked directories and junctions, and compare symlinked files.
+    pub follow_symlinks: bool,
+    /// Mention devices, sockets and FIFOs found while walking, skipped either way.
+    pub warn_special: bool,
+    /// Never walk inside macOS bundles such as `.app` or `.photoslibrary` directories.
+    pub skip_bundles: bool,
+    /// Only files whose contents are of this kind are compared.
+    pub file_type: Option<FileKind>,
+    /// Skip what git ignores, through `.gitignore` files and the global ignore file.
+    pub respect_gitignore: bool,
+    /// Skip what `.fdupignore` files, in gitignore syntax, list.
+    pub fdupignore: bool,
+    /// Which files found are compared.
+    pub filters: Filters,
+    pub use_mmap: bool,
+    pub algorithm: HashAlgorithm,
+    pub buffer_size: BufferSize,
+    /// Read files through io_uring while hashing them in full.
+    pub io_uring: bool,
+    /// Bytes compared at the end of candidates before hashing them in full, if any.
+    pub tail_check: Option<u64>,
+    /// Where full hashes are cached between runs, `None` when caching is disabled.
+    pub cache_path: Option<PathBuf>,
+    /// Reuse the results of the previous scan of the same path.
+    pub incremental: bool,
+    /// Compare sampled chunks of files instead of their whole contents.
+    pub estimate: bool,
+    /// Compare images by what they show instead of their bytes.
+    pub images: Option<ImageMode>,
+    /// Bits perceptual hashes may differ by for `--images similar`.
+    pub image_distance: u32,
+    /// Compare audio files by what they sound like instead of their bytes.
+    pub audio: Option<AudioMode>,
+    /// Compare videos by what they show instead of their bytes.
+    pub video: Option<VideoMode>,
+    /// Compare text files by what they say instead of their bytes.
+    pub text: Option<TextMode>,
+    /// Percentage of shingles documents need in common for `--text similar`.
+    pub text_similarity: u32,
+    /// Hash files locked by another process again once the others are done.
+    pub retry_locked: bool,
+    /// Only report duplicates found within a single directory.
+    pub same_dir: bool,
+    /// Only report duplicates spread over several directories.
+    pub cross_dir: bool,
+    /// Also report directories whose contents are identical.
+    pub dirs: bool,
+    /// Compare the files of every group byte by byte before reporting it.
+    pub verify: bool,
+    /// Report groups while scanning instead of once everything is hashed. Always set for
+    /// JSON Lines, which is written as the scan goes.
+    pub stream: bool,
+    /// How results are written to stdout.
+    pub format: OutputFormat,
+    /// Layout of the line printed per file, set along with the template format.
+    pub template: Option<Template>,
+    /// Start each group with the size of its files, in the fdupes format.
+    pub show_size: bool,
+    /// Leave the groups out of the text output, only printing the totals.
+    pub quiet: bool,
+    /// Print the totals of the scan along with the groups in the text output.
+    pub summary: bool,
+    /// Highlight parts of the text output with ANSI colors.
+    pub color: bool,
+    /// Report paths relative to the scan root they were found under.
+    pub relative: bool,
+    /// File the report is written to instead of stdout.
+    pub output_path: Option<PathBuf>,
+    /// Order the groups are printed in, unless streamed.
+    pub sort: GroupOrder,
+    /// Print the groups in the opposite order.
+    pub reverse: bool,
+    /// Only report the groups with the most reclaimable space, this many of them.
+    pub top: Option<usize>,
+    /// What to do with the copies not kept once the scan is over, and how those kept are
+    /// chosen.
+    pub action: Option<(Action, Selection)>,
+    /// Files never removed by `action`.
+    pub protected: Protected,
+    /// Directories whose copies `--keep` picks first, most preferred first. Absolute.
+    pub preferred: Vec<PathBuf>,
+    /// Only list what `action` would do, leaving every file as it is.
+    pub dry_run: bool,
+    /// Compare every file with the kept copy byte by byte right before acting on it.
+    pub paranoid: bool,
+    /// Only act on groups whose extra copies take at least this many bytes.
+    pub min_savings: Option<u64>,
+    /// Carry the times and extended attributes of the copies acted upon over to the kept one.
+    pub merge_metadata: bool,
+    /// Shell to write the commands deleting the copies not kept for, in place of the
+    /// report, instead of deleting them.
+    pub script: Option<Shell>,
+    /// Where every file acted upon is recorded for `fdup undo`, `None` when disabled.
+    pub journal_path: Option<PathBuf>,
+    /// Command run for every duplicate or every group once the scan is over.
+    pub exec: Option<(ExecCommand, ExecScope)>,
+    /// Where to also write the results as an HTML page.
+    pub report_path: Option<PathBuf>,
+    /// SQLite database to also write the results to.
+    pub sqlite_path: Option<PathBuf>,
+    /// Checksum file to also write the hash of every duplicate to.
+    pub manifest_path: Option<PathBuf>,
+    /// Where the state of the scan is saved periodically, to be resumed if it stops early.
+    pub checkpoint_path: Option<PathBuf>,
+    /// Continue the scan saved at `checkpoint_path` instead of starting over.
+    pub resume: bool,
+    /// Number of threads walking, hashing and verifying.
+    pub threads: usize,
+    /// Files walkers, hashers and verifiers may hold open at once.
+    pub max_open_files: usize,
+    /// Storage the scanned files are assumed to live on, to bound reads per device.
+    pub device_profile: DeviceProfile,
+    /// Approximate memory the file list may take before it is spilled to disk.
+    pub memory_limit: Option<u64>,
+    /// Stop walking once this many files are found, comparing only those.
+    pub max_files: Option<u64>,
+    /// Stop the whole scan once it has run for this long.
+    pub max_duration: Option<Duration>,
+    /// Run at low CPU and I/O priority.
+    pub nice: bool,
+    /// Print statistics about the scan once it is over.
+    pub stats: bool,
+    /// Draw a progress line on stderr while scanning.
+    pub progress: bool,
+    /// Most detailed messages shown on stderr.
+    pub verbosity: Level,
+    /// File every message is also written to.
+    pub log_file: Option<PathBuf>,
+}
+
+impl Config {
+    /// Build the configuration from the parsed CLI arguments.
+    /// Fails with a printable message when a value can not be understood.
+    pub fn from_args(args: &ArgMap) -> Result<Config, String> {
+        let algorithm = match args.get::<String>("algorithm") {
+            Ok(name) => name.parse()?,
+            Err(_) => HashAlgorithm::Sha256,
+        };
+        let template = match args.get::<String>("template") {
+            Ok(template) => Some(template.parse::<Template>()?),
+            Err(_) => None,
+        };
+        let format = match (
+            args.get::<String>("format"),
+            args.has_arg("print0"),
+            template.is_some(),
+        ) {
+            (Ok(_), true, _) => {
+                return Err("--print0 can not be combined with --format".to_string());
+            }
+            (Ok(_), _, true) => {
+                return Err("--template can not be combined with --format".to_string());
+            }
+            (_, true, true) => {
+                return Err("--template can not be combined with --print0".to_string());
+            }
+            (Ok(name), false, false) => name.parse()?,
+            (Err(_), true, false) => OutputFormat::Print0,
+            (Err(_), false, true) => OutputFormat::Template,
+            (Err(_), false, false) => OutputFormat::Text,
+        };
+        let buffer_size = match args.get::<String>("buffer-size") {
+            Ok(size) => size.parse()?,
+            Err(_) => BufferSize::Adaptive,
+        };
+        let tail_check = match args.get::<String>("tail-check") {
+            Ok(size) => Some(parse_size(&size)?).filter(|&size| size > 0),
+            Err(_) => None,
+        };
+        let min_size = match args.get::<String>("min-size") {
+            Ok(size) => Some(parse_size(&size)?),
+            Err(_) => None,
+        };
+        let max_size = match args.get::<String>("max-size") {
+            Ok(size) => Some(parse_size(&size)?),
+            Err(_) => None,
+        };
+        if let (Some(min), Some(max)) = (min_size, max_size)
+            && min > max
+        {
+            return Err("--min-size can not be larger than --max-size".to_string());
+        }
+        let extensions = args
+            .get::<String>("ext")
+            .ok()
+            .map(|list| parse_extensions(&list));
+        let excluded_extensions = args
+            .get::<String>("exclude-ext")
+            .map(|list| parse_extensions(&list))
+            .unwrap_or_default();
+        let include = match args.get::<String>("include") {
+            Ok(pattern) => Some(parse_glob(&pattern)?),
+            Err(_) => None,
+        };
+        let exclude = match args.get::<String>("exclude") {
+            Ok(pattern) => Some(parse_glob(&pattern)?),
+            Err(_) => None,
+        };
+        let name_match = match args.get::<String>("match") {
+            Ok(expression) => Some(parse_regex(&expression)?),
+            Err(_) => None,
+        };
+        let name_not_match = match args.get::<String>("not-match") {
+            Ok(expression) => Some(parse_regex(&expression)?),
+            Err(_) => None,
+        };
+        let excluded_dirs: Vec<String> = args.get_all::<String>("exclude-dir").unwrap_or_default();
+        if excluded_dirs.iter().any(String::is_empty) {
+            return Err("--exclude-dir can not be empty".to_string());
+        }
+        let (excluded_dir_names, excluded_dir_paths) = parse_excluded_dirs(excluded_dirs);
+        let max_depth = match args.get::<String>("max-depth") {
+            Ok(depth) => match depth.parse::<usize>() {
+                Ok(depth) => Some(depth),
+                Err(_) => {
+                    return Err(format!(
+                        "Invalid depth `{depth}`, expected a number of directory levels"
+                    ));
+                }
+            },
+            Err(_) => None,
+        };
+        if max_depth.is_some() && args.get::<bool>("recurse").is_err() {
+            return Err("--max-depth only applies along with --recurse".to_string());
+        }
+        let modified_before = match args.get::<String>("older-than") {
+            Ok(time) => Some(parse_time(&time)?),
+            Err(_) => None,
+        };
+        let modified_after = match args.get::<String>("newer-than") {
+            Ok(time) => Some(parse_time(&time)?),
+            Err(_) => None,
+        };
+        if let (Some(before), Some(after)) = (modified_before, modified_after)
+            && before <= after
+        {
+            return Err("--older-than and --newer-than leave no time in between".to_string());
+        }
+        let file_type = match args.get::<String>("type") {
+            Ok(name) => Some(name.parse()?),
+            Err(_) => None,
+        };
+        let (owner, group) = owner_filters(args)?;
+        let cache_path = if args.has_arg("no-cache") {
+            None
+        } else {
+            match args.get::<String>("cache-file") {
+                Ok(location) => Some(PathBuf::from(location)),
+                Err(_) => cache::default_cache_path(),
+            }
+        };
+
+        // Resuming keeps updating the checkpoint it started from.
+        let resume = args.get::<String>("resume").ok().map(PathBuf::from);
+        let checkpoint_path = resume
+            .clone()
+            .or_else(|| args.get::<String>("checkpoint").ok().map(PathBuf::from));
+        let threads = match args.get::<String>("threads") {
+            Ok(count) => match count.parse::<usize>() {
+                Ok(count) if count > 0 => count,
+                _ => {
+                    return Err(format!(
+                        "Invalid thread count `{count}`, expected a positive number"
+                    ));
+                }
+            },
+            Err(_) => thread::available_parallelism()
+                .map(NonZeroUsize::get)
+                .unwrap_or(1),
+        };
+        let io_uring = args.has_arg("io-uring");
+        if io_uring && !cfg!(target_os = "linux") {
+            return Err("--io-uring is only available on Linux".to_string());
+        }
+        // Mount points are told by the device of each directory, unknown elsewhere.
+        if args.has_arg("one-file-system") && !cfg!(any(unix, target_os = "windows")) {
+            return Err("--one-file-system is only available on Unix and Windows".to_string());
+        }
+        // Half the process limit leaves room for everything else it keeps open.
+        let max_open_files = match args.get::<String>("max-open-files") {
+            Ok(count) => match count.parse::<usize>() {
+                Ok(count) if count > 0 => count,
+                _ => {
+                    return Err(format!(
+                        "Invalid open file count `{count}`, expected a positive number"
+                    ));
+                }
+            },
+            Err(_) => platform::open_file_limit()
+                .map_or(DEFAULT_MAX_OPEN_FILES, |limit| (limit / 2).max(1) as usize),
+        };
+        let device_profile = match args.get::<String>("device-profile") {
+            Ok(name) => name.parse()?,
+            Err(_) => DeviceProfile::Auto,
+        };
+        let memory_limit = match args.get::<String>("memory-limit") {
+            Ok(size) => Some(parse_size(&size)?),
+            Err(_) => None,
+        };
+        let max_files = match args.get::<String>("max-files") {
+            Ok(count) => match count.parse::<u64>() {
+                Ok(count) if count > 0 => Some(count),
+                _ => {
+                    return Err(format!(
+                        "Invalid file count `{count}`, expected a positive number"
+                    ));
+                }
+            },
+            Err(_) => None,
+        };
+        let max_duration = match args.get::<String>("max-duration") {
+            Ok(limit) => Some(parse_duration(&limit)?),
+            Err(_) => None,
+        };
+        // A walk cut short on purpose would leave the checkpoint with nothing to resume.
+        if max_files.is_some() && checkpoint_path.is_some() {
+            return Err(
+                "--max-files can not be combined with --checkpoint or --resume".to_string(),
+            );
+        }
+        if args.has_arg("same-dir") && args.has_arg("cross-dir") {
+            return Err("--same-dir and --cross-dir can not be combined".to_string());
+        }
+        let estimate = args.has_arg("estimate");
+        let quiet = args.has_arg("quiet");
+        if quiet && args.has_arg("no-summary") {
+            return Err("--quiet and --no-summary can not be combined".to_string());
+        }
+        if (quiet || args.has_arg("no-summary")) && format != OutputFormat::Text {
+            return Err("--quiet and --no-summary only apply to the text format".to_string());
+        }
+        let sort = match args.get::<String>("sort") {
+            Ok(name) => name.parse()?,
+            Err(_) => GroupOrder::Size,
+        };
+        // Streamed groups are printed as they are found, there is nothing left to sort.
+        if (args.has_arg("sort") || args.has_arg("reverse"))
+            && (args.has_arg("stream") || format == OutputFormat::JsonLines)
+        {
+            return Err(
+                "--sort and --reverse can not be combined with --stream or --format jsonl"
+                    .to_string(),
+            );
+        }
+        // Directories are only known to be identical once every group is.
+        if args.has_arg("dirs") {
+            if args.has_arg("stream") || format == OutputFormat::JsonLines {
+                return Err(
+                    "--dirs can not be combined with --stream or --format jsonl".to_string()
+                );
+            }
+            if !matches!(format, OutputFormat::Text | OutputFormat::Json) {
+                return Err("--dirs only applies to the text and JSON formats".to_string());
+            }
+            if args.has_arg("same-dir") {
+                return Err("--dirs and --same-dir can not be combined".to_string());
+            }
+        }
+        let top = match args.get::<String>("top") {
+            Ok(count) => match count.parse::<usize>() {
+                Ok(count) if count > 0 => Some(count),
+                _ => {
+                    return Err(format!(
+                        "Invalid group count `{count}`, expected a positive number"
+                    ));
+                }
+            },
+            Err(_) => None,
+        };
+        // The largest groups are only known once every group is.
+        if top.is_some() && (args.has_arg("stream") || format == OutputFormat::JsonLines) {
+            return Err("--top can not be combined with --stream or --format jsonl".to_string());
+        }
+        let keep = match args.get::<String>("keep") {
+            Ok(name) => Some(name.parse::<KeepPolicy>()?),
+            Err(_) => None,
+        };
+        let action = match (
+            args.has_arg("delete"),
+            args.get::<String>("link"),
+            args.get::<String>("move-to"),
+        ) {
+            (true, Err(_), Err(_)) if args.has_arg("trash") => Some(Action::Trash),
+            (true, Err(_), Err(_)) => Some(Action::Delete),
+            (false, Ok(kind), Err(_)) => Some(Action::Link(kind.parse()?)),
+            (false, Err(_), Ok(quarantine)) => Some(Action::Move(PathBuf::from(quarantine))),
+            (false, Err(_), Err(_)) => None,
+            _ => {
+                return Err("--delete, --link and --move-to can not be combined".to_string());
+            }
+        };
+        if args.has_arg("trash") && action != Some(Action::Trash) {
+            return Err("--trash only applies along with --delete".to_string());
+        }
+        let action = match (action, args.get::<String>("link-style")) {
+            (Some(Action::Link(LinkKind::Soft(_))), Ok(style)) => {
+                Some(Action::Link(LinkKind::Soft(style.parse()?)))
+            }
+            (_, Ok(_)) => {
+                return Err("--link-style only applies along with --link soft".to_string());
+            }
+            (action, Err(_)) => action,
+        };
+        let action = match (
+            action,
+            args.has_arg("interactive"),
+            args.has_arg("edit"),
+            keep,
+        ) {
+            (Some(_), true, true, _) => {
+                return Err("--interactive and --edit can not be combined".to_string());
+            }
+            (Some(_), true, false, Some(_)) => {
+                return Err("--interactive and --keep can not be combined".to_string());
+            }
+            (Some(action), true, false, None) => Some((action, Selection::Interactive)),
+            // --keep only picks the copies marked to start with.
+            (Some(action), false, true, policy) => {
+                Some((action, Selection::Edit(policy.unwrap_or(KeepPolicy::First))))
+            }
+            (Some(action), false, false, Some(policy)) => Some((action, Selection::Keep(policy))),
+            (Some(_), false, false, None) => {
+                return Err(
+                    "--delete, --link and --move-to need --interactive, --edit or --keep to choose which copies to keep"
+                        .to_string(),
+                );
+            }
+            (None, true, _, _) => {
+                return Err(
+                    "--interactive only applies along with --delete, --link or --move-to"
+                        .to_string(),
+                );
+            }
+            (None, false, true, _) => {
+                return Err(
+                    "--edit only applies along with --delete, --link or --move-to".to_string(),
+                );
+            }
+            (None, false, false, Some(_)) => {
+                return Err(
+                    "--keep only applies along with --delete, --link or --move-to".to_string(),
+                );
+            }
+            (None, false, false, None) => None,
+        };
+        // Every directory is given to its own --prefer, most preferred first.
+        let preferred: Vec<String> = args.get_all::<String>("prefer").unwrap_or_default();
+        if !preferred.is_empty() && keep.is_none() {
+            return Err("--prefer only applies along with --keep".to_string());
+        }
+        if preferred.iter().any(String::is_empty) {
+            return Err("--prefer can not be empty".to_string());
+        }
+        let preferred: Vec<PathBuf> = preferred
+            .into_iter()
+            .map(|directory| {
+                std::path::absolute(&directory).unwrap_or_else(|_| PathBuf::from(directory))
+            })
+            .collect();
+        let protected = Protected {
+            pattern: match args.get::<String>("keep-pattern") {
+                Ok(pattern) => Some(parse_glob(&pattern)?),
+                Err(_) => None,
+            },
+            expression: match args.get::<String>("keep-regex") {
+                Ok(expression) => Some(parse_regex(&expression)?),
+                Err(_) => None,
+            },
+        };
+        if action.is_none() && (protected.pattern.is_some() || protected.expression.is_some()) {
+            return Err(
+                "--keep-pattern and --keep-regex only apply along with --delete, --link or --move-to"
+                    .to_string(),
+            );
+        }
+        let min_savings = match args.get::<String>("min-savings") {
+            Ok(size) => Some(parse_size(&size)?),
+            Err(_) => None,
+        };
+        if action.is_none() && min_savings.is_some() {
+            return Err(
+                "--min-savings only applies along with --delete, --link or --move-to".to_string(),
+            );
+        }
+        if action.is_none() && args.has_arg("merge-metadata") {
+            return Err(
+                "--merge-metadata only applies along with --delete, --link or --move-to"
+                    .to_string(),
+            );
+        }
+        if action.is_none() && args.has_arg("paranoid") {
+            return Err(
+                "--paranoid only applies along with --delete, --link or --move-to".to_string(),
+            );
+        }
+        if action.is_none() && args.has_arg("dry-run") {
+            return Err(
+                "--dry-run only applies along with --delete, --link or --move-to".to_string(),
+            );
+        }
+        let script = match args.get::<String>("script") {
+            Ok(name) => Some(name.parse::<Shell>()?),
+            Err(_) => None,
+        };
+        if script.is_some() && !matches!(action, Some((Action::Delete, Selection::Keep(_)))) {
+            return Err("--script only applies along with --delete and --keep".to_string());
+        }
+        if script.is_some() && args.has_arg("dry-run") {
+            return Err("--script and --dry-run can not be combined".to_string());
+        }
+        // The script is written where the report would go.
+        if script.is_some()
+            && (format != OutputFormat::Text || args.has_arg("stream") || args.has_arg("quiet"))
+        {
+            return Err(
+                "--script can not be combined with --format, --print0, --template, --stream or --quiet"
+                    .to_string(),
+            );
+        }
+        let journal_path = if args.has_arg("no-journal") {
+            None
+        } else {
+            match args.get::<String>("journal") {
+                Ok(location) => Some(PathBuf::from(location)),
+                Err(_) => cache::default_journal_path(),
+            }
+        };
+        if action.is_none() && (args.has_arg("journal") || args.has_arg("no-journal")) {
+            return Err(
+                "--journal and --no-journal only apply along with --delete, --link or --move-to"
+                    .to_string(),
+            );
+        }
+        let exec = match (args.get::<String>("exec"), args.get::<String>("exec-group")) {
+            (Ok(_), Ok(_)) => {
+                return Err("--exec and --exec-group can not be combined".to_string());
+            }
+            (Ok(command), Err(_)) => Some((command.parse()?, ExecScope::Duplicate)),
+            (Err(_), Ok(command)) => Some((command.parse()?, ExecScope::Group)),
+            (Err(_), Err(_)) => None,
+        };
+        if estimate {
+            refuse_estimated(action.is_some(), exec.is_some())?;
+        }
+        let output_path = args.get::<String>("output").ok().map(PathBuf::from);
+        let manifest_path = args.get::<String>("write-manifest").ok().map(PathBuf::from);
+        // Sampled hashes are not the digest of the whole file any checksum tool computes.
+        if estimate && manifest_path.is_some() {
+            return Err("--estimate can not be combined with --write-manifest".to_string());
+        }
+        // Sampled hashes must never be mistaken for full ones by a later scan.
+        if estimate && (args.has_arg("incremental") || checkpoint_path.is_some()) {
+            return Err(
+                "--estimate can not be combined with --incremental, --checkpoint or --resume"
+                    .to_string(),
+            );
+        }
+        // Checkpoints record the whole file list, which would defeat the limit.
+        if memory_limit.is_some() && checkpoint_path.is_some() {
+            return Err(
+                "--memory-limit can not be combined with --checkpoint or --resume".to_string(),
+            );
+        }
+        let images = match args.get::<String>("images") {
+            Ok(mode) => Some(mode.parse::<ImageMode>()?),
+            Err(_) => None,
+        };
+        let image_distance = match args.get::<String>("image-distance") {
+            Ok(_) if images != Some(ImageMode::Similar) => {
+                return Err("--image-distance only applies along with --images similar".to_string());
+            }
+            Ok(bits) => match bits.parse::<u32>() {
+                Ok(bits) if bits <= 64 => bits,
+                _ => {
+                    return Err(format!(
+                        "Invalid image distance `{bits}`, expected a number of bits from 0 to 64"
+                    ));
+                }
+            },
+            Err(_) => images::DEFAULT_DISTANCE,
+        };
+        let audio = match args.get::<String>("audio") {
+            Ok(mode) => Some(mode.parse::<AudioMode>()?),
+            Err(_) => None,
+        };
+        let video = match args.get::<String>("video") {
+            Ok(mode) => Some(mode.parse::<VideoMode>()?),
+            Err(_) => None,
+        };
+        let text = match args.get::<String>("text") {
+            Ok(mode) => Some(mode.parse::<TextMode>()?),
+            Err(_) => None,
+        };
+        let text_similarity = match args.get::<String>("text-similarity") {
+            Ok(_) if text != Some(TextMode::Similar) => {
+                return Err("--text-similarity only applies along with --text similar".to_string());
+            }
+            Ok(percent) => match percent.trim_end_matches('%').parse::<u32>() {
+                Ok(percent) if (1..=100).contains(&percent) => percent,
+                _ => {
+                    return Err(format!(
+                        "Invalid text similarity `{percent}`, expected a percentage from 1 to 100"
+                    ));
+                }
+            },
+            Err(_) => text::DEFAULT_SIMILARITY,
+        };
+        // Files compared by what they show, sound like or say do not hold the same
+        // bytes, nothing can be done with them that assumes they do.
+        let by_content: Vec<&str> = [
+            (images.is_some(), "--images"),
+            (audio.is_some(), "--audio"),
+            (video.is_some(), "--video"),
+            (text.is_some(), "--text"),
+        ]
+        .into_iter()
+        .filter_map(|(given, option)| given.then_some(option))
+        .collect();
+        let by_content = match by_content[..] {
+            [] => None,
+            [option] => Some(option),
+            _ => {
+                return Err("--images, --audio, --video and --text can not be combined".to_string());
+            }
+        };
+        if let Some(option) = by_content {
+            if action.is_some() || exec.is_some() {
+                return Err(format!(
+                    "{option} can not be combined with --delete, --link, --move-to, --exec or --exec-group"
+                ));
+            }
+            if estimate
+                || args.has_arg("verify")
+                || args.has_arg("stream")
+                || format == OutputFormat::JsonLines
+                || manifest_path.is_some()
+                || args.has_arg("incremental")
+                || checkpoint_path.is_some()
+                || args.has_arg("same-dir")
+                || args.has_arg("dirs")
+            {
+                return Err(format!(
+                    "{option} can not be combined with --estimate, --verify, --stream, --format jsonl, --write-manifest, --incremental, --checkpoint, --same-dir or --dirs"
+                ));
+            }
+        }
+
+        let files_from = args.get::<String>("files-from").ok().map(PathBuf::from);
+        // The list holds every file there is, nothing is walked to be checkpointed.
+        if files_from.is_some() && checkpoint_path.is_some() {
+            return Err(
+                "--files-from can not be combined with --checkpoint or --resume".to_string(),
+            );
+        }
+        // Answers would be read from the list.
+        if files_from.as_deref() == Some(Path::new("-"))
+            && matches!(action, Some((_, Selection::Interactive)))
+        {
+            return Err("--files-from - can not be combined with --interactive".to_string());
+        }
+        // Several paths are given by repeating --path, each taken as it is. Listed files
+        // are only matched against them, the current directory by default.
+        let mut roots: Vec<PathBuf> = match (args.get_all::<String>("path"), &files_from) {
+            (Ok(paths), _) => paths.into_iter().map(PathBuf::from).collect(),
+            (Err(_), Some(_)) => vec![PathBuf::from(".")],
+            (Err(_), None) => {
+                return Err("--path is required, unless given --files-from".to_string());
+            }
+        };
+        if roots.iter().any(|root| root.as_os_str().is_empty()) {
+            return Err("--path can not be empty".to_string());
+        }
+        let path = roots[0].clone();
+        let reference = args.get::<String>("reference").ok().map(PathBuf::from);
+        if let Some(reference) = &reference {
+            if args.has_arg("same-dir") {
+                return Err("--reference and --same-dir can not be combined".to_string());
+            }
+            let canonical = |path: &Path| {
+                std::fs::canonicalize(path)
+                    .map_err(|err| format!("Invalid path `{}`: {err}", path.to_string_lossy()))
+            };
+            let original = canonical(reference)?;
+            // Listed files only default to the current directory, which may well hold
+            // the reference: those listed in it are taken as originals.
+            let given_roots = match args.has_arg("path") {
+                true => roots.as_slice(),
+                false => &[],
+            };
+            for root in given_roots {
+                let scanned = canonical(root)?;
+                // Files of one would be found under the other, telling them apart.
+                if scanned.starts_with(&original) || original.starts_with(&scanned) {
+                    return Err("--reference can not overlap the paths being scanned".to_string());
+                }
+            }
+        }
+        roots.extend(reference.clone());
+
+        Ok(Config {
+            roots,
+            files_from,
+            reference,
+            path,
+            recurse: args.get::<bool>("recurse").is_ok(),
+            max_depth,
+            include_hidden: args.has_arg("include-hidden"),
+            one_file_system: args.has_arg("one-file-system"),
+            follow_symlinks: args.has_arg("follow-symlinks"),
+            warn_special: args.has_arg("warn-special"),
+            skip_bundles: args.has_arg("skip-bundles"),
+            file_type,
+            respect_gitignore: args.has_arg("respect-gitignore"),
+            fdupignore: !args.has_arg("no-fdupignore"),
+            filters: Filters {
+                include_empty: args.has_arg("include-empty"),
+                min_size,
+                max_size,
+                modified_before,
+                modified_after,
+                owner,
+                group,
+                extensions,
+                excluded_extensions,
+                include,
+                exclude,
+                name_match,
+                name_not_match,
+                excluded_dir_names,
+                excluded_dir_paths,
+            },
+            use_mmap: !args.has_arg("no-mmap"),
+            algorithm,
+            buffer_size,
+            io_uring,
+            tail_check,
+            cache_path,
+            incremental: args.has_arg("incremental"),
+            estimate,
+            images,
+            image_distance,
+            audio,
+            video,
+            text,
+            text_similarity,
+            retry_locked: args.has_arg("retry-locked"),
+            same_dir: args.has_arg("same-dir"),
+            cross_dir: args.has_arg("cross-dir"),
+            dirs: args.has_arg("dirs"),
+            verify: args.has_arg("verify"),
+            stream: args.has_arg("stream") || format == OutputFormat::JsonLines,
+            format,
+            template,
+            show_size: args.has_arg("show-size"),
+            quiet,
+            summary: !args.has_arg("no-summary"),
+            sort,
+            reverse: args.has_arg("reverse"),
+            top,
+            action,
+            protected,
+            preferred,
+            dry_run: args.has_arg("dry-run"),
+            paranoid: args.has_arg("paranoid"),
+            min_savings,
+            merge_metadata: args.has_arg("merge-metadata"),
+            script,
+            journal_path,
+            exec,
+            // A file is no terminal, whatever stdout is.
+            color: match args.get::<String>("color") {
+                Ok(choice) => choice.parse()?,
+                Err(_) => ColorChoice::Auto,
+            }
+            .enabled(output_path.is_none()),
+            output_path,
+            relative: args.has_arg("relative"),
+            report_path: args.get::<String>("report").ok().map(PathBuf::from),
+            sqlite_path: args.get::<String>("export-sqlite").ok().map(PathBuf::from),
+            manifest_path,
+            checkpoint_path,
+            resume: resume.is_some(),
+            threads,
+            max_open_files,
+            device_profile,
+            memory_limit,
+            max_files,
+            max_duration,
+            nice: args.has_arg("nice"),
+            stats: args.has_arg("stats"),
+            progress: args.has_arg("progress"),
+            verbosity: match (args.has_arg("debug"), args.count("verbose")) {
+                (true, _) | (false, 2..) => Level::Debug,
+                (false, 1) => Level::Info,
+                (false, 0) => Level::Warn,
+            },
+            log_file: args.get::<String>("log-file").ok().map(PathBuf::from),
+        })
+    }
+
+    /// Whether `path` was found in the `--reference` directory.
+    pub fn in_reference(&self, path: &Path) -> bool {
+        self.reference
+            .as_ref()
+            .is_some_and(|reference| path.starts_with(reference))
+    }
+
+    /// How files are read while computing full hashes.
+    pub fn read_options(&self) -> ReadOptions {
+        ReadOptions {
+            use_mmap: self.use_mmap,
+            buffer_size: self.buffer_size,
+            parallel: self.threads > 1,
+            io_uring: self.io_uring,
+        }
+    }
+}
+
+/// Refuse to act on files whose hashes were only sampled, they are not known to be
+/// identical: `acting` along with --delete, --link or --move-to, `running` commands
+/// along with --exec or --exec-group.
+fn refuse_estimated(acting: bool, running: bool) -> Result<(), String> {
+    if acting {
+        return Err(
+            "--estimate can not be combined with --delete, --link or --move-to".to_string(),
+        );
+    }
+    if running {
+        return Err("--estimate can not be combined with --exec or --exec-group".to_string());
+    }
+    Ok(())
+}
+
+/// User and group ids files must be owned by, from `--owner` and `--group`.
+#[cfg(unix)]
+fn owner_filters(args: &ArgMap) -> Result<(Option<u32>, Option<u32>), String> {
+    let owner = match args.get::<String>("owner") {
+        Ok(name) => Some(platform::user_id(&name).ok_or(format!("Unknown user `{name}`"))?),
+        Err(_) => None,
+    };
+    let group = match args.get::<String>("group") {
+        Ok(name) => Some(platform::group_id(&name).ok_or(format!("Unknown group `{name}`"))?),
+        Err(_) => None,
+    };
+    Ok((owner, group))
+}
+
+/// User and group ids files must be owned by, from `--owner` and `--group`.
+#[cfg(not(unix))]
+fn owner_filters(args: &ArgMap) -> Result<(Option<u32>, Option<u32>), String> {
+    if args.has_arg("owner") || args.has_arg("group") {
+        return Err("--owner and --group are only available on Unix".to_string());
+    }
+    Ok((None, None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimated_groups_are_never_acted_on() {
+        assert!(refuse_estimated(true, false).is_err());
+        assert!(refuse_estimated(false, true).is_err());
+        assert!(refuse_estimated(false, false).is_ok());
+    }
+}
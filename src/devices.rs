@@ -0,0 +1,124 @@
+use std::{
+    collections::BTreeMap,
+    str::FromStr,
+    sync::{Condvar, Mutex, PoisonError},
+};
+
+use crate::platform;
+
+/// Files read at once from a single spinning disk. Any more and the heads spend their
+/// time seeking between them instead of reading.
+const SPINNING_DISK_READERS: usize = 1;
+
+/// What kind of storage the scanned files are assumed to live on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeviceProfile {
+    /// Detect spinning disks per device where the platform allows it.
+    Auto,
+    /// Treat every device as a spinning disk.
+    Hdd,
+    /// Treat every device as solid state.
+    Ssd,
+}
+
+impl FromStr for DeviceProfile {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "auto" => Ok(DeviceProfile::Auto),
+            "hdd" => Ok(DeviceProfile::Hdd),
+            "ssd" => Ok(DeviceProfile::Ssd),
+            _ => Err(format!(
+                "Unknown device profile `{name}`, expected one of: auto, hdd, ssd"
+            )),
+        }
+    }
+}
+
+/// Files being read from every spinning disk, shared by all threads.
+struct Devices {
+    state: Mutex<State>,
+    released: Condvar,
+}
+
+struct State {
+    profile: DeviceProfile,
+    /// Readers currently busy on each spinning disk.
+    readers: BTreeMap<u64, usize>,
+}
+
+static DEVICES: Devices = Devices {
+    state: Mutex::new(State {
+        profile: DeviceProfile::Auto,
+        readers: BTreeMap::new(),
+    }),
+    released: Condvar::new(),
+};
+
+/// Assume `profile` for every device from now on.
+pub fn set_profile(profile: DeviceProfile) {
+    let mut state = DEVICES.state.lock().unwrap_or_else(PoisonError::into_inner);
+    state.profile = profile;
+}
+
+/// Whether `device` is treated as a spinning disk. Unknown devices are only treated as
+/// one when every device is.
+pub fn is_rotational(device: Option<u64>) -> bool {
+    let profile = DEVICES
+        .state
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .profile;
+    rotational(profile, device)
+}
+
+fn rotational(profile: DeviceProfile, device: Option<u64>) -> bool {
+    match profile {
+        DeviceProfile::Auto => device.is_some_and(platform::is_rotational),
+        DeviceProfile::Hdd => true,
+        DeviceProfile::Ssd => false,
+    }
+}
+
+/// Permission to read from a device, handed back when dropped.
+pub struct Permit {
+    device: Option<u64>,
+}
+
+/// Wait until a file on `device` may be read. Spinning disks are read by a bounded
+/// number of threads at once, other devices by as many as want to.
+pub fn acquire(device: Option<u64>) -> Permit {
+    let mut state = DEVICES.state.lock().unwrap_or_else(PoisonError::into_inner);
+    if !rotational(state.profile, device) {
+        return Permit { device: None };
+    }
+    // Devices can not be told apart here, they all share the same bound.
+    let device = device.unwrap_or(0);
+    while state.readers.get(&device).copied().unwrap_or(0) >= SPINNING_DISK_READERS {
+        state = DEVICES
+            .released
+            .wait(state)
+            .unwrap_or_else(PoisonError::into_inner);
+    }
+    *state.readers.entry(device).or_default() += 1;
+    Permit {
+        device: Some(device),
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let Some(device) = self.device else {
+            return;
+        };
+        let mut state = DEVICES.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(readers) = state.readers.get_mut(&device) {
+            *readers -= 1;
+            if *readers == 0 {
+                state.readers.remove(&device);
+            }
+        }
+        DEVICES.released.notify_all();
+    }
+}
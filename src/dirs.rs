@@ -0,0 +1,201 @@
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{DuplicateGroup, hash::to_hex, walk::root_of};
+
+/// Directories holding the same files under the same names, subdirectories included.
+pub struct DirectoryGroup {
+    pub directories: Vec<PathBuf>,
+    /// Files found in each of the directories.
+    pub files: usize,
+    /// Bytes taken by each of the directories.
+    pub size: u64,
+}
+
+impl DirectoryGroup {
+    /// Space taken by every copy of the directory but one.
+    pub fn wasted_bytes(&self) -> u64 {
+        (self.directories.len() as u64 - 1) * self.size
+    }
+}
+
+/// What is known of a directory holding duplicates.
+#[derive(Default)]
+struct Directory<'a> {
+    /// Name and content of every file inside.
+    files: Vec<(&'a OsStr, String)>,
+    size: u64,
+    subdirectories: Vec<PathBuf>,
+    /// It holds something with no copy anywhere, so no other directory can match it.
+    unique: bool,
+}
+
+impl Directory<'_> {
+    /// Whether the directory at `path` holds anything besides the duplicates and
+    /// subdirectories known: hidden, empty or filtered out files, symbolic links,
+    /// special files or directories where no duplicate was found. Files the scan never
+    /// looked at can not be told identical to anything, so they make it unique, and so
+    /// does a directory that can not be listed.
+    fn holds_unknown(&self, path: &Path) -> bool {
+        let Ok(entries) = fs::read_dir(path) else {
+            return true;
+        };
+        let files: HashSet<&OsStr> = self.files.iter().map(|(name, _)| *name).collect();
+        let subdirectories: HashSet<&OsStr> = self
+            .subdirectories
+            .iter()
+            .filter_map(|subdirectory| subdirectory.file_name())
+            .collect();
+        for entry in entries {
+            let Ok(entry) = entry else {
+                return true;
+            };
+            let Ok(kind) = entry.file_type() else {
+                return true;
+            };
+            let name = entry.file_name();
+            let known = match (kind.is_file(), kind.is_dir()) {
+                (true, _) => files.contains(name.as_os_str()),
+                (_, true) => subdirectories.contains(name.as_os_str()),
+                _ => false,
+            };
+            if !known {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// Every group of directories whose contents are identical, as told by rolling the hashes
+/// of the duplicates in `groups` up into a hash per directory. A directory holding
+/// anything that is not among the duplicates, whether the scan saw it or not, can not
+/// have a copy, nor can the directories above it. Only the topmost directories of
+/// identical trees are reported, and directories are walked up to the scan `roots` at
+/// most.
+pub fn find_duplicate_dirs(groups: &[DuplicateGroup], roots: &[PathBuf]) -> Vec<DirectoryGroup> {
+    let mut directories: HashMap<PathBuf, Directory> = HashMap::new();
+    for group in groups {
+        let hash = group.hash.to_string();
+        for file in &group.files {
+            let (Some(parent), Some(name)) = (file.path.parent(), file.path.file_name()) else {
+                continue;
+            };
+            let directory = directories.entry(parent.to_path_buf()).or_default();
+            directory.files.push((name, hash.clone()));
+            directory.size += file.size;
+        }
+    }
+    // Link every directory to the one above it, up to the root it was found under.
+    let mut pending: Vec<PathBuf> = directories.keys().cloned().collect();
+    while let Some(path) = pending.pop() {
+        let Some(root) = root_of(&path, roots) else {
+            continue;
+        };
+        if path == root {
+            continue;
+        }
+        let Some(parent) = path.parent() else {
+            continue;
+        };
+        if !directories.contains_key(parent) {
+            pending.push(parent.to_path_buf());
+        }
+        let parent = directories.entry(parent.to_path_buf()).or_default();
+        parent.subdirectories.push(path);
+    }
+
+    // Listed again rather than trusting the walk, which leaves out whatever its filters
+    // skip: a copy missing any of it is no copy.
+    for (path, directory) in &mut directories {
+        directory.unique = directory.holds_unknown(path);
+    }
+
+    // Subdirectories are hashed before the directories holding them.
+    let mut order: Vec<&PathBuf> = directories.keys().collect();
+    order.sort_by_key(|path| Reverse(path.components().count()));
+    let mut digests: HashMap<&Path, (String, usize, u64)> = HashMap::new();
+    for path in order {
+        let directory = &directories[path];
+        if directory.unique {
+            continue;
+        }
+        let mut entries: Vec<(&OsStr, char, &str)> = directory
+            .files
+            .iter()
+            .map(|(name, hash)| (*name, 'f', hash.as_str()))
+            .collect();
+        let mut files = directory.files.len();
+        let mut size = directory.size;
+        let mut complete = true;
+        for subdirectory in &directory.subdirectories {
+            match (
+                digests.get(subdirectory.as_path()),
+                subdirectory.file_name(),
+            ) {
+                (Some((digest, count, bytes)), Some(name)) => {
+                    entries.push((name, 'd', digest.as_str()));
+                    files += count;
+                    size += bytes;
+                }
+                _ => complete = false,
+            }
+        }
+        if !complete || entries.is_empty() {
+            continue;
+        }
+        entries.sort_unstable();
+        let mut hasher = blake3::Hasher::new();
+        for (name, kind, digest) in entries {
+            hasher.update(name.as_encoded_bytes());
+            hasher.update(&[0, kind as u8, 0]);
+            hasher.update(digest.as_bytes());
+            hasher.update(b"\n");
+        }
+        let digest = to_hex(hasher.finalize().as_bytes());
+        digests.insert(path.as_path(), (digest, files, size));
+    }
+
+    let mut by_digest: HashMap<&str, Vec<&Path>> = HashMap::new();
+    for (path, (digest, _, _)) in &digests {
+        by_digest.entry(digest.as_str()).or_default().push(path);
+    }
+    by_digest.retain(|_, paths| paths.len() > 1);
+    // Within identical directories every subdirectory has a copy too, only the
+    // directories above them are worth reporting.
+    let duplicated: HashSet<&Path> = by_digest.values().flatten().copied().collect();
+    let mut result: Vec<DirectoryGroup> = by_digest
+        .into_values()
+        .filter_map(|paths| {
+            let mut topmost: Vec<PathBuf> = paths
+                .iter()
+                .filter(|path| {
+                    path.parent()
+                        .is_none_or(|parent| !duplicated.contains(parent))
+                })
+                .map(|path| path.to_path_buf())
+                .collect();
+            if topmost.len() < 2 {
+                return None;
+            }
+            topmost.sort();
+            let (_, files, size) = &digests[paths[0]];
+            Some(DirectoryGroup {
+                directories: topmost,
+                files: *files,
+                size: *size,
+            })
+        })
+        .collect();
+    result.sort_by(|a, b| {
+        b.wasted_bytes()
+            .cmp(&a.wasted_bytes())
+            .then_with(|| a.directories.cmp(&b.directories))
+    });
+    result
+}
@@ -0,0 +1,246 @@
+use std::{
+    collections::HashMap,
+    env,
+    fs::{self, File, OpenOptions},
+    hash::{BuildHasher, RandomState},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{DuplicateGroup, actions::KeepPolicy, config::Config};
+
+/// Explains the file opened in the editor, written at its top.
+const INSTRUCTIONS: &str = "\
+# Choose which copies to keep, then save and close the editor.
+#
+# keep <path> = keep this copy
+# drop <path> = act on this copy
+#
+# Every group must keep at least one copy. Files left out are kept, and nothing
+# is done at all if every line is removed.
+";
+
+/// Names tried for the selection before giving up, should others already be taken.
+const CREATE_ATTEMPTS: u32 = 16;
+
+/// Editor used when neither `$VISUAL` nor `$EDITOR` is set.
+#[cfg(target_os = "windows")]
+const DEFAULT_EDITOR: &str = "notepad";
+#[cfg(not(target_os = "windows"))]
+const DEFAULT_EDITOR: &str = "vi";
+
+/// Let the user choose the copies kept in every group by editing a list of them, the
+/// way `git rebase -i` works. Copies are marked as `policy` would keep them to start
+/// with. Returns the indexes of the files kept per group, `None` when nothing is to be
+/// done.
+pub fn edit_selection(
+    groups: &[DuplicateGroup],
+    policy: KeepPolicy,
+    config: &Config,
+) -> Result<Option<Vec<Vec<usize>>>, String> {
+    let (location, file) = create_selection()
+        .map_err(|err| format!("Error creating selection in the temporary directory: {err}"))?;
+    if let Err(err) = write_selection(file, groups, policy, config) {
+        let _ = fs::remove_file(&location);
+        return Err(format!(
+            "Error writing selection: `{}` {err}",
+            location.to_string_lossy()
+        ));
+    }
+    let edited = open_editor(&location).and_then(|()| {
+        fs::read_to_string(&location).map_err(|err| {
+            format!(
+                "Error reading selection: `{}` {err}",
+                location.to_string_lossy()
+            )
+        })
+    });
+    let _ = fs::remove_file(&location);
+    parse_selection(&edited?, groups)
+}
+
+/// A new file for the selection in the temporary directory, under a name no one can
+/// guess. It is never one that already exists, nor a symbolic link planted there by
+/// someone else sharing the directory.
+fn create_selection() -> io::Result<(PathBuf, File)> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        // Paths in the selection are nobody else's business.
+        options.mode(0o600);
+    }
+    let random = RandomState::new();
+    let mut attempt = 0;
+    loop {
+        let location = env::temp_dir().join(format!(
+            "fdup-edit-{}-{:016x}.txt",
+            std::process::id(),
+            random.hash_one(attempt)
+        ));
+        match options.open(&location) {
+            Ok(file) => return Ok((location, file)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                attempt += 1;
+                if attempt == CREATE_ATTEMPTS {
+                    return Err(err);
+                }
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Write every group to `file`, one `keep` or `drop` line per file. Paths that would
+/// not read back the same are only mentioned in a comment, and always kept.
+fn write_selection(
+    file: File,
+    groups: &[DuplicateGroup],
+    policy: KeepPolicy,
+    config: &Config,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(file);
+    write!(writer, "{INSTRUCTIONS}")?;
+    for (index, group) in groups.iter().enumerate() {
+        let kept = policy.choose(group, config);
+        writeln!(writer, "\n# Group {} of {}", index + 1, groups.len())?;
+        for (position, file) in group.files.iter().enumerate() {
+            match editable(&file.path) {
+                Some(path) if position == kept => writeln!(writer, "keep {path}")?,
+                Some(path) => writeln!(writer, "drop {path}")?,
+                None => writeln!(
+                    writer,
+                    "# Always kept, its path can not be edited: {}",
+                    file.path.to_string_lossy().replace(['\n', '\r'], "?")
+                )?,
+            }
+        }
+    }
+    writer.flush()
+}
+
+/// `path` as written in the selection, as long as it reads back the same.
+fn editable(path: &Path) -> Option<&str> {
+    path.to_str()
+        .filter(|path| !path.contains(['\n', '\r']) && path.trim() == *path)
+}
+
+/// Open the editor chosen by `$VISUAL` or `$EDITOR` on `location` and wait for it to
+/// close. The variables may hold arguments, such as `code --wait`.
+fn open_editor(location: &Path) -> Result<(), String> {
+    let editor = env::var("VISUAL")
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .or_else(|| env::var("EDITOR").ok())
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_EDITOR.to_string());
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or(DEFAULT_EDITOR);
+    let status = Command::new(program)
+        .args(words)
+        .arg(location)
+        .status()
+        .map_err(|err| format!("Error running editor `{program}`: {err}"))?;
+    if !status.success() {
+        return Err(format!("Editor `{program}` failed: {status}"));
+    }
+    Ok(())
+}
+
+/// Indexes of the files kept per group according to the edited `text`. `None` when
+/// every line was removed.
+fn parse_selection(
+    text: &str,
+    groups: &[DuplicateGroup],
+) -> Result<Option<Vec<Vec<usize>>>, String> {
+    let positions: HashMap<&Path, (usize, usize)> = groups
+        .iter()
+        .enumerate()
+        .flat_map(|(index, group)| {
+            group
+                .files
+                .iter()
+                .enumerate()
+                .map(move |(position, file)| (file.path.as_path(), (index, position)))
+        })
+        .collect();
+    // Files are kept unless told otherwise.
+    let mut dropped = vec![Vec::new(); groups.len()];
+    let mut any_line = false;
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        any_line = true;
+        let (command, path) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let path = PathBuf::from(path.trim());
+        let Some(&(index, position)) = positions.get(path.as_path()) else {
+            return Err(format!(
+                "Line {}: `{}` is not part of any group",
+                number + 1,
+                path.to_string_lossy()
+            ));
+        };
+        match command {
+            "keep" | "k" => {}
+            "drop" | "d" => dropped[index].push(position),
+            _ => {
+                return Err(format!(
+                    "Line {}: unknown command `{command}`, expected keep or drop",
+                    number + 1
+                ));
+            }
+        }
+    }
+    if !any_line {
+        return Ok(None);
+    }
+    let mut kept = Vec::with_capacity(groups.len());
+    for (index, (group, dropped)) in groups.iter().zip(dropped).enumerate() {
+        let positions: Vec<usize> = (0..group.files.len())
+            .filter(|position| !dropped.contains(position))
+            .collect();
+        if positions.is_empty() {
+            return Err(format!("Group {} keeps no copy", index + 1));
+        }
+        kept.push(positions);
+    }
+    Ok(Some(kept))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::group_of;
+
+    #[test]
+    fn dropping_every_copy_is_refused() {
+        let groups = [group_of(&["a", "b"])];
+        let err = parse_selection("drop a\ndrop b\n", &groups).unwrap_err();
+        assert_eq!(err, "Group 1 keeps no copy");
+    }
+
+    #[test]
+    fn emptied_selection_does_nothing() {
+        let groups = [group_of(&["a", "b"])];
+        assert_eq!(parse_selection("# comment\n\n", &groups), Ok(None));
+    }
+
+    #[test]
+    fn files_left_out_are_kept() {
+        let groups = [group_of(&["a", "b", "c"])];
+        assert_eq!(
+            parse_selection("drop b\n", &groups),
+            Ok(Some(vec![vec![0, 2]]))
+        );
+    }
+
+    #[test]
+    fn unknown_paths_are_refused() {
+        let groups = [group_of(&["a", "b"])];
+        assert!(parse_selection("drop z\n", &groups).is_err());
+    }
+}
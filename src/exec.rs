@@ -0,0 +1,124 @@
+use std::{ffi::OsString, path::Path, process::Command, str::FromStr};
+
+use crate::{
+    DuplicateGroup,
+    actions::Selection,
+    config::Config,
+    interrupt::{self, StopReason},
+    log,
+    report::group_id,
+};
+
+/// How often a `--exec` command runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExecScope {
+    /// Once for every copy but the kept one, `{}` standing for that copy.
+    Duplicate,
+    /// Once for every group, `{}` standing for all of its files.
+    Group,
+}
+
+/// Command run by `--exec` and `--exec-group`, split into words the way a shell would:
+/// on whitespace, except within single or double quotes. `{}` stands for the files,
+/// `{kept}` for the copy kept and `{group}` for the identifier of the group.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExecCommand(Vec<String>);
+
+impl FromStr for ExecCommand {
+    type Err = String;
+
+    fn from_str(command: &str) -> Result<Self, Self::Err> {
+        let mut words = Vec::new();
+        let mut word: Option<String> = None;
+        let mut quote = None;
+        for c in command.chars() {
+            match (quote, c) {
+                (Some(open), c) if c == open => quote = None,
+                (Some(_), c) => word.get_or_insert_default().push(c),
+                (None, '\'' | '"') => {
+                    quote = Some(c);
+                    word.get_or_insert_default();
+                }
+                (None, c) if c.is_whitespace() => words.extend(word.take()),
+                (None, c) => word.get_or_insert_default().push(c),
+            }
+        }
+        if quote.is_some() {
+            return Err(format!("Invalid command `{command}`, a quote is left open"));
+        }
+        words.extend(word);
+        if words.is_empty() {
+            return Err("The command to run can not be empty".to_string());
+        }
+        Ok(ExecCommand(words))
+    }
+}
+
+impl ExecCommand {
+    /// Arguments of the command for `files`. A word that is only `{}` becomes one
+    /// argument per file, elsewhere the paths are joined with spaces.
+    fn expand(&self, files: &[&Path], kept: &Path, group: &str) -> Vec<OsString> {
+        let mut args = Vec::new();
+        for word in &self.0 {
+            match word.as_str() {
+                "{}" => args.extend(files.iter().map(|file| file.as_os_str().to_owned())),
+                "{kept}" => args.push(kept.as_os_str().to_owned()),
+                word => {
+                    let files: Vec<_> = files.iter().map(|file| file.to_string_lossy()).collect();
+                    args.push(OsString::from(
+                        word.replace("{kept}", &kept.to_string_lossy())
+                            .replace("{group}", group)
+                            .replace("{}", &files.join(" ")),
+                    ));
+                }
+            }
+        }
+        args
+    }
+}
+
+/// Run the `--exec` command of `config` for every group or every copy but the kept one,
+/// as its scope says. The copy kept is the one `--keep` picks, the first one otherwise.
+pub fn run(groups: &[DuplicateGroup], config: &Config) {
+    let Some((command, scope)) = &config.exec else {
+        return;
+    };
+    for group in groups {
+        if interrupt::stop_reason() == Some(StopReason::Interrupted) {
+            break;
+        }
+        let kept = match config.action {
+            Some((_, Selection::Keep(policy) | Selection::Edit(policy))) => {
+                policy.choose(group, config)
+            }
+            _ => 0,
+        };
+        let kept_path = &group.files[kept].path;
+        let id = group_id(group, config);
+        let runs: Vec<Vec<&Path>> = match scope {
+            ExecScope::Duplicate => group
+                .files
+                .iter()
+                .enumerate()
+                .filter(|&(position, _)| position != kept)
+                .map(|(_, file)| vec![file.path.as_path()])
+                .collect(),
+            ExecScope::Group => vec![group.files.iter().map(|file| file.path.as_path()).collect()],
+        };
+        for files in runs {
+            let args = command.expand(&files, kept_path, &id);
+            match Command::new(&args[0]).args(&args[1..]).status() {
+                Ok(status) if status.success() => {}
+                Ok(status) => log::warn!(
+                    "`{}` failed for group {id}: {status}",
+                    args[0].to_string_lossy()
+                ),
+                // It would fail the same way for every other group.
+                Err(err) => {
+                    log::error!("Error running `{}`: {err}", args[0].to_string_lossy());
+                    return;
+                }
+            }
+        }
+    }
+}
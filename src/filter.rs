@@ -0,0 +1,173 @@
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use globset::{GlobBuilder, GlobMatcher};
+use regex::Regex;
+
+/// Which of the files found are compared at all. Everything here is decided from the
+/// directory entry and its metadata, files left out are never opened.
+#[derive(Default)]
+pub struct Filters {
+    /// Compare empty files too. They are all identical, so they are left out by default.
+    pub include_empty: bool,
+    /// Smallest file size compared, in bytes.
+    pub min_size: Option<u64>,
+    /// Largest file size compared, in bytes.
+    pub max_size: Option<u64>,
+    /// Only files modified before this are compared.
+    pub modified_before: Option<SystemTime>,
+    /// Only files modified after this are compared.
+    pub modified_after: Option<SystemTime>,
+    /// Only files owned by this user id are compared.
+    pub owner: Option<u32>,
+    /// Only files owned by this group id are compared.
+    pub group: Option<u32>,
+    /// Only files with one of these extensions are compared, when set. Lowercase.
+    pub extensions: Option<Vec<String>>,
+    /// Files with one of these extensions are never compared. Lowercase.
+    pub excluded_extensions: Vec<String>,
+    /// Only files matching this pattern are compared, when set.
+    pub include: Option<GlobMatcher>,
+    /// Files matching this pattern are never compared, nor directories walked.
+    pub exclude: Option<GlobMatcher>,
+    /// Only files whose name matches this expression are compared, when set.
+    pub name_match: Option<Regex>,
+    /// Files whose name matches this expression are never compared.
+    pub name_not_match: Option<Regex>,
+    /// Directories never walked into, by name.
+    pub excluded_dir_names: Vec<String>,
+    /// Directories never walked into, by absolute path.
+    pub excluded_dir_paths: Vec<PathBuf>,
+}
+
+impl Filters {
+    /// Whether a file of `size` bytes is to be compared.
+    pub fn accepts_size(&self, size: u64) -> bool {
+        (size > 0 || self.include_empty)
+            && self.min_size.is_none_or(|min| size >= min)
+            && self.max_size.is_none_or(|max| size <= max)
+    }
+
+    /// Whether a file last modified at `modified` is to be compared. Files without a
+    /// modification time are left out as soon as a range is set.
+    pub fn accepts_modified(&self, modified: Option<SystemTime>) -> bool {
+        if self.modified_before.is_none() && self.modified_after.is_none() {
+            return true;
+        }
+        modified.is_some_and(|modified| {
+            self.modified_before.is_none_or(|before| modified < before)
+                && self.modified_after.is_none_or(|after| modified > after)
+        })
+    }
+
+    /// Whether a file owned by `owner`, a `(user, group)` pair of ids, is to be compared.
+    pub fn accepts_owner(&self, owner: Option<(u32, u32)>) -> bool {
+        if self.owner.is_none() && self.group.is_none() {
+            return true;
+        }
+        owner.is_some_and(|(user, group)| {
+            self.owner.is_none_or(|owner| user == owner)
+                && self.group.is_none_or(|wanted| group == wanted)
+        })
+    }
+
+    /// Whether the file at `path` is to be compared, judging by its extension alone.
+    /// Extensions compare without regard to case.
+    pub fn accepts_extension(&self, path: &Path) -> bool {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        let listed = |list: &[String]| {
+            extension
+                .as_ref()
+                .is_some_and(|extension| list.contains(extension))
+        };
+        self.extensions.as_deref().is_none_or(listed) && !listed(&self.excluded_extensions)
+    }
+
+    /// Whether the file at `relative`, the path from its scan root, matches the patterns
+    /// and its name the expressions.
+    pub fn accepts_file(&self, relative: &Path) -> bool {
+        let name = relative
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        self.include
+            .as_ref()
+            .is_none_or(|include| include.is_match(relative))
+            && !self.excluded(relative)
+            && self
+                .name_match
+                .as_ref()
+                .is_none_or(|expression| expression.is_match(&name))
+            && !self
+                .name_not_match
+                .as_ref()
+                .is_some_and(|expression| expression.is_match(&name))
+    }
+
+    /// Whether the directory at `path`, `relative` from its scan root, is to be skipped
+    /// along with everything inside.
+    pub fn excludes_directory(&self, path: &Path, relative: &Path) -> bool {
+        let named = path.file_name().is_some_and(|name| {
+            self.excluded_dir_names
+                .iter()
+                .any(|excluded| name == excluded.as_str())
+        });
+        named
+            || self.excluded(relative)
+            || (!self.excluded_dir_paths.is_empty()
+                && std::path::absolute(path)
+                    .is_ok_and(|path| self.excluded_dir_paths.contains(&path)))
+    }
+
+    /// Whether `relative` matches the exclusion pattern.
+    fn excluded(&self, relative: &Path) -> bool {
+        self.exclude
+            .as_ref()
+            .is_some_and(|exclude| exclude.is_match(relative))
+    }
+}
+
+/// Parse a comma separated list of extensions such as `jpg,.PNG`, leading dots optional.
+pub fn parse_extensions(text: &str) -> Vec<String> {
+    text.split(',')
+        .map(|extension| extension.trim().trim_start_matches('.').to_lowercase())
+        .filter(|extension| !extension.is_empty())
+        .collect()
+}
+
+/// Split directories such as `node_modules` and `/mnt/backup` into names and paths.
+/// Entries holding a path separator are paths, made absolute against the current
+/// directory, the others are names matched wherever they appear.
+pub fn parse_excluded_dirs(entries: Vec<String>) -> (Vec<String>, Vec<PathBuf>) {
+    let mut names = Vec::new();
+    let mut paths = Vec::new();
+    for entry in entries {
+        if entry.contains(std::path::is_separator) {
+            let path = Path::new(&entry);
+            paths.push(std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()));
+        } else {
+            names.push(entry);
+        }
+    }
+    (names, paths)
+}
+
+/// Compile a pattern such as `**/*.iso`. Like in a shell, `*` and `?` stay within a
+/// directory, while `**` spans any number of them.
+pub fn parse_glob(pattern: &str) -> Result<GlobMatcher, String> {
+    GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .build()
+        .map(|glob| glob.compile_matcher())
+        .map_err(|err| format!("Invalid pattern `{pattern}`: {err}"))
+}
+
+/// Compile an expression matched against file names, such as `IMG_\d{4}\.jpg`.
+/// It matches anywhere in the name unless anchored with `^` and `$`.
+pub fn parse_regex(expression: &str) -> Result<Regex, String> {
+    Regex::new(expression).map_err(|err| format!("Invalid expression `{expression}`: {err}"))
+}
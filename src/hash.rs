@@ -0,0 +1,403 @@
+use std::{
+    fmt,
+    fs::{File, Metadata},
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+    str::FromStr,
+};
+
+use md5::Md5;
+use memmap2::Mmap;
+use sha2::{Digest, Sha256, Sha512};
+use xxhash_rust::xxh3::Xxh3;
+
+use crate::{devices, interrupt, open_files, platform, progress::PROGRESS, units::parse_size};
+
+/// Files at least this large are hashed through a memory map instead of a read loop.
+const MMAP_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+/// Files at least this large are hashed by several threads when the algorithm allows it.
+const PARALLEL_HASH_THRESHOLD: u64 = 128 * 1024 * 1024;
+
+/// Size of the chunks handed to a multi-threaded hasher when the file is not memory mapped.
+const PARALLEL_CHUNK_SIZE: usize = 16 * 1024 * 1024;
+
+/// Smallest chunk read at once by the adaptive buffer strategy.
+const MIN_ADAPTIVE_BUFFER: usize = 64 * 1024;
+
+/// Largest chunk read at once by the adaptive buffer strategy, on solid state storage
+/// and on spinning disks where longer reads save seeks.
+const MAX_ADAPTIVE_BUFFER: usize = 1024 * 1024;
+const MAX_ROTATIONAL_BUFFER: usize = 8 * 1024 * 1024;
+
+/// How much of a file is read at once while hashing or comparing it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BufferSize {
+    /// Always read chunks of this many bytes.
+    Fixed(usize),
+    /// Scale chunks with the size of the file, up to a limit depending on the device.
+    Adaptive,
+}
+
+impl BufferSize {
+    /// Size of the chunks to read the open `file`, described by `meta`, with.
+    pub fn chunk_size(self, file: &File, meta: &Metadata) -> usize {
+        match self {
+            BufferSize::Fixed(size) => size,
+            BufferSize::Adaptive => {
+                let device = platform::open_device_id(file, meta);
+                let limit = match devices::is_rotational(device) {
+                    true => MAX_ROTATIONAL_BUFFER,
+                    false => MAX_ADAPTIVE_BUFFER,
+                };
+                (meta.len().min(limit as u64) as usize)
+                    .next_power_of_two()
+                    .clamp(MIN_ADAPTIVE_BUFFER, limit)
+            }
+        }
+    }
+}
+
+impl FromStr for BufferSize {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        if text.eq_ignore_ascii_case("auto") {
+            return Ok(BufferSize::Adaptive);
+        }
+        match parse_size(text)? {
+            0 => Err("The buffer size must be at least one byte".to_string()),
+            size => Ok(BufferSize::Fixed(size.try_into().unwrap_or(usize::MAX))),
+        }
+    }
+}
+
+/// How file contents are read while computing full hashes.
+#[derive(Clone, Copy, Debug)]
+pub struct ReadOptions {
+    /// Memory map large files instead of reading them.
+    pub use_mmap: bool,
+    pub buffer_size: BufferSize,
+    /// Hash very large files on several threads when the algorithm allows it.
+    pub parallel: bool,
+    /// Read files through io_uring, only available on Linux.
+    pub io_uring: bool,
+}
+
+/// Hash algorithms available to compare file contents.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HashAlgorithm {
+    Sha256,
+    Sha512,
+    Blake3,
+    Xxh3,
+    Md5,
+}
+
+impl HashAlgorithm {
+    /// Every supported algorithm.
+    pub const ALL: [HashAlgorithm; 5] = [
+        HashAlgorithm::Sha256,
+        HashAlgorithm::Sha512,
+        HashAlgorithm::Blake3,
+        HashAlgorithm::Xxh3,
+        HashAlgorithm::Md5,
+    ];
+
+    /// Name used on the command line and in reports.
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Sha512 => "sha512",
+            HashAlgorithm::Blake3 => "blake3",
+            HashAlgorithm::Xxh3 => "xxh3",
+            HashAlgorithm::Md5 => "md5",
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            "sha512" => Ok(HashAlgorithm::Sha512),
+            "blake3" => Ok(HashAlgorithm::Blake3),
+            "xxh3" => Ok(HashAlgorithm::Xxh3),
+            "md5" => Ok(HashAlgorithm::Md5),
+            _ => Err(format!(
+                "Unknown hash algorithm `{name}`, expected one of: sha256, sha512, blake3, xxh3, md5"
+            )),
+        }
+    }
+}
+
+/// Digest of a file's contents.
+/// The algorithm is part of the value, so digests from different algorithms never compare equal.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FileHash {
+    pub algorithm: HashAlgorithm,
+    pub digest: String,
+}
+
+impl fmt::Display for FileHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.algorithm, self.digest)
+    }
+}
+
+/// Running hash state for any of the supported algorithms.
+pub enum Hasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+    Blake3(Box<blake3::Hasher>),
+    Xxh3(Box<Xxh3>),
+    Md5(Md5),
+}
+
+impl Hasher {
+    pub fn new(algorithm: HashAlgorithm) -> Hasher {
+        match algorithm {
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Sha512 => Hasher::Sha512(Sha512::new()),
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+            HashAlgorithm::Xxh3 => Hasher::Xxh3(Box::new(Xxh3::new())),
+            HashAlgorithm::Md5 => Hasher::Md5(Md5::new()),
+        }
+    }
+
+    /// Whether the algorithm can spread hashing a single input over several threads.
+    fn supports_parallel(&self) -> bool {
+        matches!(self, Hasher::Blake3(_))
+    }
+
+    /// Same as `update`, using multiple threads when the algorithm supports it.
+    /// Only worth it for large inputs, small ones are slower than a plain update.
+    fn update_parallel(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Blake3(hasher) => {
+                hasher.update_rayon(data);
+            }
+            _ => self.update(data),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Sha512(hasher) => hasher.update(data),
+            Hasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+            Hasher::Xxh3(hasher) => hasher.update(data),
+            Hasher::Md5(hasher) => hasher.update(data),
+        }
+    }
+
+    /// Finish hashing and render the digest as text.
+    pub fn finish(self) -> FileHash {
+        let (algorithm, digest) = match self {
+            Hasher::Sha256(hasher) => (HashAlgorithm::Sha256, to_hex(&hasher.finalize())),
+            Hasher::Sha512(hasher) => (HashAlgorithm::Sha512, to_hex(&hasher.finalize())),
+            Hasher::Blake3(hasher) => (HashAlgorithm::Blake3, to_hex(hasher.finalize().as_bytes())),
+            Hasher::Xxh3(hasher) => (
+                HashAlgorithm::Xxh3,
+                to_hex(&hasher.digest128().to_be_bytes()),
+            ),
+            Hasher::Md5(hasher) => (HashAlgorithm::Md5, to_hex(&hasher.finalize())),
+        };
+        FileHash { algorithm, digest }
+    }
+}
+
+/// Determine the hash for a given file
+/// Large files are memory mapped when `use_mmap` is set, so the hasher is fed the
+/// whole file at once instead of going through many small reads. Very large files
+/// are hashed by multiple threads when the algorithm supports it (BLAKE3) and
+/// `parallel` is set. Other files are read in chunks sized by `buffer_size`, through
+/// io_uring when enabled.
+pub fn get_file_hash(
+    path: &Path,
+    algorithm: HashAlgorithm,
+    options: ReadOptions,
+) -> std::io::Result<FileHash> {
+    let _permit = open_files::acquire(1);
+    let mut file = platform::open_sequential(path)?;
+    let meta = file.metadata()?;
+    let size = meta.len();
+    let mut hasher = Hasher::new(algorithm);
+    // Hashing threads would read a spinning disk out of order, seeking all the way.
+    let parallel = options.parallel
+        && hasher.supports_parallel()
+        && size >= PARALLEL_HASH_THRESHOLD
+        && !devices::is_rotational(platform::open_device_id(&file, &meta));
+
+    if options.use_mmap && size >= MMAP_THRESHOLD {
+        // SAFETY: the map is only read from. A file truncated by another process while
+        // being hashed may fault, `--no-mmap` avoids this on volatile or network storage.
+        if let Ok(map) = unsafe { Mmap::map(&file) } {
+            if parallel {
+                hasher.update_parallel(&map[..]);
+            } else {
+                hasher.update(&map[..]);
+            }
+            PROGRESS.add_bytes(size);
+            return Ok(hasher.finish());
+        }
+        // Mapping is not supported everywhere, quietly fall back to reading.
+    }
+
+    if parallel {
+        let mut buffer = vec![0; PARALLEL_CHUNK_SIZE];
+        loop {
+            check_interrupted()?;
+            let read_bytes = read_full(&mut file, &mut buffer)?;
+            if read_bytes == 0 {
+                break;
+            }
+            hasher.update_parallel(&buffer[..read_bytes]);
+            PROGRESS.add_bytes(read_bytes as u64);
+        }
+        return Ok(hasher.finish());
+    }
+
+    let chunk_size = options.buffer_size.chunk_size(&file, &meta);
+    #[cfg(target_os = "linux")]
+    if options.io_uring {
+        let read = crate::uring::read_file(&file, size, chunk_size, |chunk| {
+            check_interrupted()?;
+            hasher.update(chunk);
+            PROGRESS.add_bytes(chunk.len() as u64);
+            Ok(())
+        })?;
+        if read {
+            return Ok(hasher.finish());
+        }
+    }
+    hash_reader(file, algorithm, chunk_size)
+}
+
+/// Fill as much of `buffer` as possible, stopping short only at the end of the input.
+pub fn read_full(reader: &mut impl Read, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read_bytes) => filled += read_bytes,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
+
+/// Determine the hash of the first `length` bytes of a given file
+pub fn get_partial_hash(
+    path: &Path,
+    algorithm: HashAlgorithm,
+    length: u64,
+    buffer_size: BufferSize,
+) -> std::io::Result<FileHash> {
+    let _permit = open_files::acquire(1);
+    let file = File::open(path)?;
+    let chunk_size = buffer_size.chunk_size(&file, &file.metadata()?);
+    let chunk_size = chunk_size.min(length.try_into().unwrap_or(usize::MAX));
+    hash_reader(file.take(length), algorithm, chunk_size)
+}
+
+/// Determine the hash of the last `length` bytes of a given file
+pub fn get_tail_hash(
+    path: &Path,
+    algorithm: HashAlgorithm,
+    length: u64,
+    buffer_size: BufferSize,
+) -> std::io::Result<FileHash> {
+    let _permit = open_files::acquire(1);
+    let mut file = File::open(path)?;
+    let meta = file.metadata()?;
+    file.seek(SeekFrom::Start(meta.len().saturating_sub(length)))?;
+    let chunk_size = buffer_size.chunk_size(&file, &meta);
+    let chunk_size = chunk_size.min(length.try_into().unwrap_or(usize::MAX));
+    hash_reader(file.take(length), algorithm, chunk_size)
+}
+
+/// Determine a hash from three `length` byte chunks of a given file, at its start, middle
+/// and end. Files too small to hold three chunks are hashed whole.
+pub fn get_sampled_hash(
+    path: &Path,
+    algorithm: HashAlgorithm,
+    length: u64,
+    buffer_size: BufferSize,
+) -> std::io::Result<FileHash> {
+    let _permit = open_files::acquire(1);
+    let mut file = File::open(path)?;
+    let meta = file.metadata()?;
+    let size = meta.len();
+    let chunk_size = buffer_size.chunk_size(&file, &meta);
+    let chunk_size = chunk_size.min(length.try_into().unwrap_or(usize::MAX));
+    if size <= length.saturating_mul(3) {
+        return hash_reader(file, algorithm, chunk_size);
+    }
+
+    let mut hasher = Hasher::new(algorithm);
+    let mut buffer = vec![0; chunk_size.max(1)];
+    for offset in [0, size / 2 - length / 2, size - length] {
+        file.seek(SeekFrom::Start(offset))?;
+        feed(&mut hasher, (&mut file).take(length), &mut buffer)?;
+    }
+    Ok(hasher.finish())
+}
+
+/// Hash `parts` one after the other, as if they were a single input.
+pub fn get_parts_hash(parts: &[&[u8]], algorithm: HashAlgorithm) -> FileHash {
+    let mut hasher = Hasher::new(algorithm);
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finish()
+}
+
+/// Hash everything readable from `reader`, `chunk_size` bytes at a time.
+fn hash_reader(
+    reader: impl Read,
+    algorithm: HashAlgorithm,
+    chunk_size: usize,
+) -> std::io::Result<FileHash> {
+    let mut buffer = vec![0; chunk_size.max(1)];
+    let mut hasher = Hasher::new(algorithm);
+    feed(&mut hasher, reader, &mut buffer)?;
+    Ok(hasher.finish())
+}
+
+/// Hand everything readable from `reader` to `hasher`, through `buffer`.
+fn feed(hasher: &mut Hasher, mut reader: impl Read, buffer: &mut [u8]) -> std::io::Result<()> {
+    loop {
+        check_interrupted()?;
+        let read_bytes = reader.read(buffer)?;
+        if read_bytes == 0 {
+            return Ok(());
+        }
+        hasher.update(&buffer[..read_bytes]);
+        PROGRESS.add_bytes(read_bytes as u64);
+    }
+}
+
+/// Give up on the file being hashed once the scan is interrupted.
+fn check_interrupted() -> std::io::Result<()> {
+    match interrupt::interrupted() {
+        true => Err(std::io::Error::other("scan interrupted")),
+        false => Ok(()),
+    }
+}
+
+/// Render bytes as lowercase hexadecimal, two digits per byte.
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
@@ -0,0 +1,98 @@
+use std::{fs, io, path::Path};
+
+use crate::{
+    DuplicateGroup, ScanResult,
+    config::Config,
+    report::{format_sizes, group_id, shown_path},
+    units::format_size,
+};
+
+/// Sorting and filtering of the table, kept inline so the report is a single file.
+const SCRIPT: &str = r#"
+const table = document.getElementById("groups");
+const rows = Array.from(table.tBodies[0].rows);
+document.getElementById("filter").addEventListener("input", (event) => {
+  const needle = event.target.value.toLowerCase();
+  for (const row of rows) {
+    row.hidden = !row.textContent.toLowerCase().includes(needle);
+  }
+});
+let sorted = { column: -1, ascending: true };
+for (const [column, header] of Array.from(table.tHead.rows[0].cells).entries()) {
+  header.addEventListener("click", () => {
+    const ascending = sorted.column === column ? !sorted.ascending : false;
+    sorted = { column, ascending };
+    const key = (row) => row.cells[column].dataset.value ?? row.cells[column].textContent;
+    rows.sort((a, b) => {
+      const [x, y] = [key(a), key(b)];
+      const order = isNaN(x) || isNaN(y) ? x.localeCompare(y) : x - y;
+      return ascending ? order : -order;
+    });
+    table.tBodies[0].append(...rows);
+  });
+}
+"#;
+
+const STYLE: &str = r#"
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; width: 100%; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; vertical-align: top; }
+th { background: #eee; cursor: pointer; user-select: none; }
+td.number { text-align: right; white-space: nowrap; }
+td.hash { font-family: monospace; font-size: 0.8em; word-break: break-all; }
+ul { margin: 0; padding-left: 1.2em; }
+#filter { margin: 1em 0; padding: 0.3em; width: 30em; max-width: 100%; }
+"#;
+
+/// Write the results to `location` as a standalone HTML page: the totals of the scan,
+/// then a table of every group which can be sorted by clicking a column header and
+/// filtered by typing part of a path or hash.
+pub fn write_report(location: &Path, result: &ScanResult, config: &Config) -> io::Result<()> {
+    let reclaimable: u64 = result.groups.iter().map(DuplicateGroup::wasted_bytes).sum();
+    let duplicate_files: usize = result
+        .groups
+        .iter()
+        .map(|group| group.files.len() - 1)
+        .sum();
+
+    let mut rows = String::new();
+    for (index, group) in result.groups.iter().enumerate() {
+        let size = group.size_range().1;
+        let waste = group.wasted_bytes();
+        let files: String = group
+            .files
+            .iter()
+            .map(|file| {
+                format!(
+                    "<li>{}</li>",
+                    escape(&shown_path(&file.path, config).to_string_lossy())
+                )
+            })
+            .collect();
+        rows.push_str(&format!(
+            "<tr><td class=\"number\">{}</td><td class=\"number\" data-value=\"{size}\">{}</td><td class=\"number\">{}</td><td class=\"number\" data-value=\"{waste}\">{}</td><td class=\"hash\">{}</td><td class=\"hash\">{}</td><td><ul>{files}</ul></td></tr>\n",
+            index + 1,
+            format_sizes(group),
+            group.files.len(),
+            format_size(waste),
+            escape(&group_id(group, config)),
+            escape(&group.hash.to_string()),
+        ));
+    }
+
+    let page = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>fdup report</title>\n<style>{STYLE}</style>\n</head>\n<body>\n<h1>Duplicate files</h1>\n<p>{} files scanned, {} groups of duplicates holding {duplicate_files} extra copies. Removing them would free {}.</p>\n<input id=\"filter\" type=\"search\" placeholder=\"Filter by path or hash\">\n<table id=\"groups\">\n<thead><tr><th>#</th><th>Size</th><th>Copies</th><th>Wasted</th><th>ID</th><th>Hash</th><th>Files</th></tr></thead>\n<tbody>\n{rows}</tbody>\n</table>\n<script>{SCRIPT}</script>\n</body>\n</html>\n",
+        result.files_scanned,
+        result.groups.len(),
+        format_size(reclaimable),
+    );
+    fs::write(location, page)
+}
+
+/// `text` with the characters HTML gives a meaning to replaced by entities.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
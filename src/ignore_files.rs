@@ -0,0 +1,157 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+use crate::log;
+
+/// Name of the ignore files fdup reads in every directory, in gitignore syntax.
+pub const FDUP_IGNORE_FILE: &str = ".fdupignore";
+
+/// Ignore rules found while walking, shared by all walker threads.
+/// Git reads `.gitignore` files from the root of a repository down to the directory of
+/// a file, the deepest rule matching the file deciding. The repository's
+/// `.git/info/exclude` and the global ignore file of the user come last.
+/// `.fdupignore` files work the same way, inside a repository or not, and take
+/// precedence over anything git ignores.
+pub struct IgnoreRules {
+    gitignore: bool,
+    fdupignore: bool,
+    global: Option<Arc<Gitignore>>,
+    /// Rules applying inside every directory looked at, by absolute path.
+    directories: Mutex<HashMap<PathBuf, Arc<DirectoryRules>>>,
+}
+
+/// Every ignore file applying to the entries of one directory, deepest first.
+pub struct DirectoryRules {
+    fdup: Vec<Arc<Gitignore>>,
+    git: Vec<Arc<Gitignore>>,
+    /// Outside of a repository git ignores nothing.
+    in_repository: bool,
+}
+
+impl DirectoryRules {
+    /// Whether the entry at `path`, an absolute path, is ignored.
+    pub fn ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let git = match self.in_repository {
+            true => self.git.as_slice(),
+            false => &[],
+        };
+        for matcher in self.fdup.iter().chain(git) {
+            let matched = matcher.matched(path, is_dir);
+            if matched.is_ignore() {
+                return true;
+            }
+            if matched.is_whitelist() {
+                return false;
+            }
+        }
+        false
+    }
+}
+
+impl IgnoreRules {
+    /// Rules from `.gitignore` files when `gitignore` is set, and from `.fdupignore`
+    /// files when `fdupignore` is.
+    pub fn new(gitignore: bool, fdupignore: bool) -> IgnoreRules {
+        let global = gitignore.then(|| {
+            let (global, err) = Gitignore::global();
+            if let Some(err) = err {
+                log::error!("Error reading the global git ignore file: {err}");
+            }
+            global
+        });
+        IgnoreRules {
+            gitignore,
+            fdupignore,
+            global: global.filter(|global| !global.is_empty()).map(Arc::new),
+            directories: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Rules for the entries of `directory`, an absolute path.
+    /// Ignore files are read once per directory, parents first.
+    pub fn for_directory(&self, directory: &Path) -> Arc<DirectoryRules> {
+        if let Some(rules) = self.cached(directory) {
+            return rules;
+        }
+        let is_repository_root = self.gitignore && directory.join(".git").exists();
+        let fdup = match self.fdupignore {
+            true => load(directory, &[directory.join(FDUP_IGNORE_FILE)]),
+            false => None,
+        };
+        let git = match self.gitignore {
+            true => {
+                let mut ignore_files = vec![directory.join(".gitignore")];
+                if is_repository_root {
+                    ignore_files.push(directory.join(".git").join("info").join("exclude"));
+                }
+                load(directory, &ignore_files)
+            }
+            false => None,
+        };
+
+        let mut rules = DirectoryRules {
+            fdup: fdup.into_iter().collect(),
+            git: git.into_iter().collect(),
+            in_repository: is_repository_root,
+        };
+        if is_repository_root {
+            rules.git.extend(self.global.clone());
+        }
+        if let Some(parent) = directory.parent() {
+            let inherited = self.for_directory(parent);
+            rules.fdup.extend(inherited.fdup.iter().cloned());
+            // Git rules stop at the root of the repository.
+            if !is_repository_root {
+                rules.git.extend(inherited.git.iter().cloned());
+                rules.in_repository = inherited.in_repository;
+            }
+        }
+        let rules = Arc::new(rules);
+        self.directories
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(directory.to_path_buf(), rules.clone());
+        rules
+    }
+
+    fn cached(&self, directory: &Path) -> Option<Arc<DirectoryRules>> {
+        self.directories
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(directory)
+            .cloned()
+    }
+}
+
+/// Rules of the `ignore_files` present in `directory`, `None` when there are none.
+fn load(directory: &Path, ignore_files: &[PathBuf]) -> Option<Arc<Gitignore>> {
+    let mut builder = GitignoreBuilder::new(directory);
+    let mut found = false;
+    for ignore_file in ignore_files.iter().filter(|file| file.is_file()) {
+        match builder.add(ignore_file) {
+            Some(err) => log::error!(
+                "Error reading ignore file: `{}` {err}",
+                ignore_file.to_string_lossy()
+            ),
+            None => found = true,
+        }
+    }
+    if !found {
+        return None;
+    }
+    match builder.build() {
+        Ok(matcher) => Some(Arc::new(matcher)),
+        Err(err) => {
+            log::error!(
+                "Error reading ignore files in: `{}` {err}",
+                directory.to_string_lossy()
+            );
+            None
+        }
+    }
+}
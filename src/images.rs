@@ -0,0 +1,199 @@
+use std::{io, path::Path, str::FromStr};
+
+use image::{DynamicImage, ImageReader, imageops::FilterType};
+
+use crate::{
+    DuplicateGroup,
+    config::Config,
+    hash::{FileHash, HashAlgorithm, get_parts_hash},
+    magic::{self, FileKind},
+    media, open_files,
+    progress::{PROGRESS, Phase},
+    walk::FileEntry,
+};
+
+/// Bits two perceptual hashes may differ by for their images to be deemed similar,
+/// unless told otherwise.
+pub const DEFAULT_DISTANCE: u32 = 10;
+
+/// How images are compared by `--images`, instead of by their bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageMode {
+    /// Grouped when they look alike, even resized, re-encoded or slightly edited.
+    Similar,
+    /// Grouped when their pixels are the same, whatever metadata their files hold.
+    Pixels,
+}
+
+impl FromStr for ImageMode {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "similar" => Ok(ImageMode::Similar),
+            "pixels" => Ok(ImageMode::Pixels),
+            _ => Err(format!(
+                "Unknown image mode `{name}`, expected one of: similar, pixels"
+            )),
+        }
+    }
+}
+
+/// Difference hash of the image at `path`: shrunk to 9 by 8 gray pixels, one bit per
+/// pair of neighbours telling whether the left one is brighter. Images that look alike
+/// get hashes differing by few bits, whatever their size or encoding.
+fn difference_hash(path: &Path) -> io::Result<u64> {
+    let image = decode(path)?;
+    let small = image.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    Ok(difference_bits(small.as_raw()))
+}
+
+/// Difference hash of 9 by 8 gray `pixels`, given row by row.
+pub fn difference_bits(pixels: &[u8]) -> u64 {
+    let mut hash = 0;
+    for row in pixels.chunks_exact(9).take(8) {
+        for pair in row.windows(2) {
+            hash = (hash << 1) | u64::from(pair[0] > pair[1]);
+        }
+    }
+    hash
+}
+
+/// Hash of the pixels of the image at `path` along with its dimensions, leaving out
+/// whatever metadata its file holds. Pixels are compared as 8 bit RGBA, so an image
+/// saved with or without an opaque alpha channel hashes the same. `None` for files that
+/// are not images.
+pub fn pixel_hash(path: &Path, algorithm: HashAlgorithm) -> io::Result<Option<FileHash>> {
+    if magic::sniff(path)? != Some(FileKind::Image) {
+        return Ok(None);
+    }
+    let pixels = decode(path)?.into_rgba8();
+    let (width, height) = pixels.dimensions();
+    Ok(Some(get_parts_hash(
+        &[&width.to_le_bytes(), &height.to_le_bytes(), pixels.as_raw()],
+        algorithm,
+    )))
+}
+
+/// The image at `path`, decoded whatever its extension says.
+fn decode(path: &Path) -> io::Result<DynamicImage> {
+    let _permit = open_files::acquire(1);
+    ImageReader::open(path)?
+        .with_guessed_format()?
+        .decode()
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Hashes arranged by their distance to one another, so those close to a hash are found
+/// without comparing it with every other one.
+#[derive(Default)]
+struct BkTree {
+    nodes: Vec<BkNode>,
+}
+
+struct BkNode {
+    hash: u64,
+    /// Index of the image the hash belongs to.
+    image: usize,
+    /// Nodes below, each along with its distance to this one.
+    children: Vec<(u32, usize)>,
+}
+
+impl BkTree {
+    fn insert(&mut self, hash: u64, image: usize) {
+        let node = BkNode {
+            hash,
+            image,
+            children: Vec::new(),
+        };
+        if self.nodes.is_empty() {
+            self.nodes.push(node);
+            return;
+        }
+        let mut current = 0;
+        loop {
+            let distance = (self.nodes[current].hash ^ hash).count_ones();
+            let child = self.nodes[current]
+                .children
+                .iter()
+                .find(|&&(edge, _)| edge == distance);
+            match child {
+                Some(&(_, child)) => current = child,
+                None => {
+                    let index = self.nodes.len();
+                    self.nodes.push(node);
+                    self.nodes[current].children.push((distance, index));
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Images whose hash differs from `hash` by at most `distance` bits.
+    fn within(&self, hash: u64, distance: u32) -> Vec<usize> {
+        let mut found = Vec::new();
+        let mut pending = match self.nodes.is_empty() {
+            true => Vec::new(),
+            false => vec![0],
+        };
+        while let Some(index) = pending.pop() {
+            let node = &self.nodes[index];
+            let here = (node.hash ^ hash).count_ones();
+            if here <= distance {
+                found.push(node.image);
+            }
+            // Only subtrees that can hold hashes close enough are worth a look.
+            pending.extend(
+                node.children
+                    .iter()
+                    .filter(|(edge, _)| edge.abs_diff(here) <= distance)
+                    .map(|&(_, child)| child),
+            );
+        }
+        found
+    }
+}
+
+/// Groups of images among `files` that look alike: whose perceptual hashes differ by at
+/// most `config.image_distance` bits, directly or through other images of the group.
+/// Each group goes by the perceptual hash of its first image, hashed again with the
+/// chosen algorithm so it reads like any other. Files that are not images are left out.
+pub fn find_similar(files: Vec<FileEntry>, config: &Config) -> Vec<DuplicateGroup> {
+    PROGRESS.start_phase(Phase::Hashing, files.iter().map(|file| file.size).sum());
+    let hashes = media::key_each(&files, config.threads, |file| {
+        match magic::sniff(&file.path)? {
+            Some(FileKind::Image) => difference_hash(&file.path).map(Some),
+            _ => Ok(None),
+        }
+    });
+    let mut tree = BkTree::default();
+    for (image, &(_, hash)) in hashes.iter().enumerate() {
+        tree.insert(hash, image);
+    }
+    let mut parents: Vec<usize> = (0..hashes.len()).collect();
+    for (image, &(_, hash)) in hashes.iter().enumerate() {
+        for other in tree.within(hash, config.image_distance) {
+            let (first, second) = (
+                media::find_set(&mut parents, image),
+                media::find_set(&mut parents, other),
+            );
+            parents[first.max(second)] = first.min(second);
+        }
+    }
+    let mut members: Vec<Vec<usize>> = vec![Vec::new(); hashes.len()];
+    for image in 0..hashes.len() {
+        let root = media::find_set(&mut parents, image);
+        members[root].push(image);
+    }
+    members
+        .into_iter()
+        .filter(|images| images.len() > 1)
+        .map(|images| DuplicateGroup {
+            hash: get_parts_hash(&[&hashes[images[0]].1.to_le_bytes()], config.algorithm),
+            files: images
+                .iter()
+                .map(|&image| files[hashes[image].0].clone())
+                .collect(),
+        })
+        .collect()
+}
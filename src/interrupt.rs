@@ -0,0 +1,88 @@
+use std::{
+    sync::atomic::{AtomicBool, AtomicU8, Ordering},
+    thread,
+    time::Duration,
+};
+
+use crate::log;
+
+/// Why a scan stopped before getting through every file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// The user pressed Ctrl+C.
+    Interrupted = 1,
+    /// The walk found as many files as `--max-files` allows.
+    MaxFiles = 2,
+    /// The scan ran for as long as `--max-duration` allows.
+    MaxDuration = 3,
+}
+
+impl StopReason {
+    fn from_u8(value: u8) -> Option<StopReason> {
+        match value {
+            1 => Some(StopReason::Interrupted),
+            2 => Some(StopReason::MaxFiles),
+            3 => Some(StopReason::MaxDuration),
+            _ => None,
+        }
+    }
+}
+
+/// Set once the scan has to stop, to the `StopReason` as a number. Zero while running.
+static STOPPED: AtomicU8 = AtomicU8::new(0);
+
+/// Whether the time limit of `stop_after` still applies, until the scan is over.
+static TIME_LIMITED: AtomicBool = AtomicBool::new(true);
+
+/// Exit code used when the scan was interrupted, as if killed by SIGINT.
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Catch Ctrl+C so a running scan winds down and reports what it found so far.
+/// A second Ctrl+C exits right away.
+pub fn install_handler() {
+    let result = ctrlc::set_handler(|| {
+        let previous = STOPPED.swap(StopReason::Interrupted as u8, Ordering::Relaxed);
+        if StopReason::from_u8(previous) == Some(StopReason::Interrupted) {
+            std::process::exit(INTERRUPTED_EXIT_CODE);
+        }
+        log::warn!("\nInterrupted, finishing up. Press Ctrl+C again to quit immediately.");
+    });
+    if let Err(err) = result {
+        log::error!("Error installing Ctrl+C handler: {err}");
+    }
+}
+
+/// Stop the scan once `limit` has passed, as if it was interrupted.
+pub fn stop_after(limit: Duration) {
+    thread::spawn(move || {
+        thread::sleep(limit);
+        if TIME_LIMITED.swap(false, Ordering::Relaxed)
+            && STOPPED
+                .compare_exchange(
+                    0,
+                    StopReason::MaxDuration as u8,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+        {
+            log::warn!("\nTime limit reached, finishing up.");
+        }
+    });
+}
+
+/// Lift the time limit of `stop_after` once the scan is over, so acting on what it found
+/// and waiting for the user to answer are never cut short.
+pub fn end_time_limit() {
+    TIME_LIMITED.store(false, Ordering::Relaxed);
+}
+
+/// Whether the scan should stop as soon as possible.
+pub fn interrupted() -> bool {
+    STOPPED.load(Ordering::Relaxed) != 0
+}
+
+/// Why the scan has to stop, `None` while it keeps going.
+pub fn stop_reason() -> Option<StopReason> {
+    StopReason::from_u8(STOPPED.load(Ordering::Relaxed))
+}
@@ -0,0 +1,295 @@
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    actions::{move_file, replace_with},
+    cache::{self, to_nanos},
+    hash::{BufferSize, FileHash, ReadOptions, get_file_hash},
+    log,
+};
+
+/// First line of every journal file.
+const HEADER: &str = "fdup journal 1";
+
+const USAGE: &str = "Usage: fdup undo [--journal <file>]";
+
+/// What was done to a file by `--delete`, `--link` or `--move-to`, enough to put it back.
+pub struct Entry<'a> {
+    /// The verb of the action, such as `delete` or `hardlink`.
+    pub action: &'a str,
+    pub hash: &'a FileHash,
+    /// Kept copy holding the same content.
+    pub survivor: &'a Path,
+    pub original: &'a Path,
+    /// Where the file went, for moves.
+    pub destination: Option<&'a Path>,
+}
+
+/// Record of every file acted upon, appended to as files are deleted, linked or moved.
+/// Each run starts with a `run` line, `fdup undo` reverts the last one.
+pub struct Journal {
+    writer: BufWriter<fs::File>,
+    /// When the run started, until its `run` line is written along with its first entry.
+    /// Runs which did nothing are left out, undoing them would undo nothing.
+    pending_run: Option<u128>,
+}
+
+impl Journal {
+    /// Open the journal at `location` for a new run, creating it if needed.
+    pub fn open(location: &Path) -> io::Result<Journal> {
+        if let Some(parent) = location.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(location)?;
+        let empty = file.metadata()?.len() == 0;
+        let mut writer = BufWriter::new(file);
+        if empty {
+            writeln!(writer, "{HEADER}")?;
+        }
+        writer.flush()?;
+        Ok(Journal {
+            writer,
+            pending_run: Some(to_nanos(SystemTime::now()).unwrap_or_default()),
+        })
+    }
+
+    /// Append `entry`, straight to disk so it survives whatever happens next. Paths are
+    /// recorded absolute, so the undo works from any directory.
+    pub fn record(&mut self, entry: &Entry) -> io::Result<()> {
+        let survivor = std::path::absolute(entry.survivor)?;
+        let original = std::path::absolute(entry.original)?;
+        let destination = entry.destination.map(std::path::absolute).transpose()?;
+        let (Some(survivor), Some(original)) = (printable(&survivor), printable(&original)) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "paths holding tabs or line breaks can not be journaled",
+            ));
+        };
+        let destination = match &destination {
+            Some(destination) => printable(destination).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "paths holding tabs or line breaks can not be journaled",
+                )
+            })?,
+            None => "-",
+        };
+        if let Some(started) = self.pending_run.take() {
+            writeln!(self.writer, "run\t{started}")?;
+        }
+        writeln!(
+            self.writer,
+            "{}\t{}\t{}\t{survivor}\t{original}\t{destination}",
+            entry.action, entry.hash.algorithm, entry.hash.digest
+        )?;
+        self.writer.flush()
+    }
+}
+
+/// Paths must fit on a single line of valid text, between tabs, to be read back.
+fn printable(path: &Path) -> Option<&str> {
+    path.to_str().filter(|path| !path.contains(['\n', '\t']))
+}
+
+/// A line of the journal read back.
+struct Recorded {
+    line: String,
+    action: String,
+    hash: FileHash,
+    survivor: PathBuf,
+    original: PathBuf,
+    destination: Option<PathBuf>,
+}
+
+/// Parse the `action, algorithm, digest, survivor, original, destination` fields of
+/// an entry line.
+fn parse_entry(line: &str) -> Option<Recorded> {
+    let mut fields = line.splitn(6, '\t');
+    let action = fields.next()?.to_string();
+    let algorithm = fields.next()?.parse().ok()?;
+    let digest = fields.next()?.to_string();
+    let survivor = PathBuf::from(fields.next()?);
+    let original = PathBuf::from(fields.next()?);
+    let destination = match fields.next()? {
+        "-" => None,
+        destination => Some(PathBuf::from(destination)),
+    };
+    Some(Recorded {
+        line: line.to_string(),
+        action,
+        hash: FileHash { algorithm, digest },
+        survivor,
+        original,
+        destination,
+    })
+}
+
+/// Run `fdup undo`: put back every file acted upon by the last run recorded in the
+/// journal, then drop that run from it. Entries that could not be undone stay, so
+/// running it again retries them.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut location = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--journal" => location = Some(PathBuf::from(args.next().ok_or(USAGE)?)),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let location = location
+        .or_else(cache::default_journal_path)
+        .ok_or("No cache directory available, pass the journal with --journal")?;
+
+    let file = fs::File::open(&location).map_err(|err| {
+        format!(
+            "Error reading journal: `{}` {err}",
+            location.to_string_lossy()
+        )
+    })?;
+    let mut lines: Vec<String> = BufReader::new(file).lines().map_while(Result::ok).collect();
+    if lines.first().map(String::as_str) != Some(HEADER) {
+        return Err(format!(
+            "`{}` is not a journal file",
+            location.to_string_lossy()
+        ));
+    }
+    let Some(start) = lines.iter().rposition(|line| line.starts_with("run\t")) else {
+        return Err("Nothing to undo".to_string());
+    };
+    let entries = lines.split_off(start + 1);
+
+    let mut failed = Vec::new();
+    let mut restored = 0;
+    // Last done, first undone.
+    for line in entries.iter().rev() {
+        // A crash may leave a truncated last line behind, it is simply skipped.
+        let Some(entry) = parse_entry(line) else {
+            continue;
+        };
+        match undo(&entry) {
+            Ok(()) => {
+                log::info!("Restored `{}`", entry.original.to_string_lossy());
+                restored += 1;
+            }
+            Err(err) => {
+                log::error!(
+                    "Error restoring file: `{}` {err}",
+                    entry.original.to_string_lossy()
+                );
+                failed.push(entry.line);
+            }
+        }
+    }
+    let unrestored = failed.len();
+    // Only the run line is left once every entry was undone.
+    if failed.is_empty() {
+        lines.pop();
+    }
+    lines.extend(failed.into_iter().rev());
+    if let Err(err) = rewrite(&location, &lines) {
+        log::error!(
+            "Error updating journal: `{}` {err}",
+            location.to_string_lossy()
+        );
+    }
+    eprintln!("Restored {restored} files, {unrestored} could not be");
+    Ok(())
+}
+
+/// Replace the journal at `location` with `lines`.
+fn rewrite(location: &Path, lines: &[String]) -> io::Result<()> {
+    let temporary = location.with_extension("tmp");
+    let mut writer = BufWriter::new(fs::File::create(&temporary)?);
+    for line in lines {
+        writeln!(writer, "{line}")?;
+    }
+    writer.flush()?;
+    drop(writer);
+    fs::rename(&temporary, location)
+}
+
+/// Put back the file of `entry`: moved and trashed files are brought back, the others
+/// are copied again from the survivor, as long as it still holds the same content.
+fn undo(entry: &Recorded) -> io::Result<()> {
+    match (entry.action.as_str(), &entry.destination) {
+        ("move", Some(destination)) => return move_file(destination, &entry.original),
+        ("trash", _) => match restore_from_trash(&entry.original) {
+            Ok(()) => return Ok(()),
+            Err(err) => log::warn!(
+                "Could not restore `{}` from the trash, copying it back instead: {err}",
+                entry.original.to_string_lossy()
+            ),
+        },
+        _ => {}
+    }
+    let options = ReadOptions {
+        use_mmap: true,
+        buffer_size: BufferSize::Adaptive,
+        parallel: true,
+        io_uring: false,
+    };
+    if get_file_hash(&entry.survivor, entry.hash.algorithm, options)? != entry.hash {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "`{}` no longer holds the content it had",
+                entry.survivor.to_string_lossy()
+            ),
+        ));
+    }
+    // Deleted files are only copied back where nothing took their place.
+    if matches!(entry.action.as_str(), "delete" | "trash")
+        && fs::symlink_metadata(&entry.original).is_ok()
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("`{}` already exists", entry.original.to_string_lossy()),
+        ));
+    }
+    replace_with(&entry.original, |copy| {
+        fs::copy(&entry.survivor, copy).map(|_| ())
+    })
+}
+
+/// Bring the file trashed from `original` back, the last one trashed from there if
+/// there are several.
+#[cfg(any(
+    target_os = "windows",
+    all(
+        unix,
+        not(target_os = "macos"),
+        not(target_os = "ios"),
+        not(target_os = "android")
+    )
+))]
+fn restore_from_trash(original: &Path) -> io::Result<()> {
+    let item = trash::os_limited::list()
+        .map_err(io::Error::other)?
+        .into_iter()
+        .filter(|item| item.original_path() == original)
+        .max_by_key(|item| item.time_deleted)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "it is not in the trash"))?;
+    trash::os_limited::restore_all([item]).map_err(io::Error::other)
+}
+
+/// Bring the file trashed from `original` back. The trash can not be listed on this
+/// platform.
+#[cfg(not(any(
+    target_os = "windows",
+    all(
+        unix,
+        not(target_os = "macos"),
+        not(target_os = "ios"),
+        not(target_os = "android")
+    )
+)))]
+fn restore_from_trash(_original: &Path) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
@@ -0,0 +1,109 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, Write},
+    path::Path,
+    sync::{
+        Mutex, PoisonError,
+        atomic::{AtomicU8, Ordering},
+    },
+    time::SystemTime,
+};
+
+use crate::units::format_time;
+
+/// How much a message matters, the terminal only shows those up to the chosen verbosity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Something failed, a file or directory could not be compared.
+    Error = 0,
+    /// Something the user should know about, the scan goes on as usual.
+    Warn = 1,
+    /// What the scan skipped and how long each step took, shown with `-v`.
+    Info = 2,
+    /// Every directory and file as it is handled, shown with `--debug`.
+    Debug = 3,
+}
+
+impl Level {
+    fn label(self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+        }
+    }
+}
+
+/// Most detailed level shown on the terminal.
+static VERBOSITY: AtomicU8 = AtomicU8::new(Level::Warn as u8);
+
+/// File every message is written to, whatever the verbosity.
+static LOG_FILE: Mutex<Option<File>> = Mutex::new(None);
+
+/// Show messages up to `verbosity` on stderr, and write all of them to a new file at
+/// `location` when given.
+pub fn init(verbosity: Level, location: Option<&Path>) -> io::Result<()> {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+    if let Some(location) = location {
+        *LOG_FILE.lock().unwrap_or_else(PoisonError::into_inner) = Some(File::create(location)?);
+    }
+    Ok(())
+}
+
+/// Whether a message at `level` would end up anywhere, to spare building it otherwise.
+pub fn enabled(level: Level) -> bool {
+    level as u8 <= VERBOSITY.load(Ordering::Relaxed)
+        || LOG_FILE
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_some()
+}
+
+/// Report `message` at `level`. Use the `error!`, `warn!`, `info!` and `debug!` macros.
+pub fn write(level: Level, message: fmt::Arguments) {
+    if level as u8 <= VERBOSITY.load(Ordering::Relaxed) {
+        eprintln!("{message}");
+    }
+    let mut file = LOG_FILE.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(file) = file.as_mut() {
+        // Written as a single line at once, so lines from different threads never mix.
+        let line = format!(
+            "{} {:<5} {message}\n",
+            format_time(SystemTime::now()),
+            level.label()
+        );
+        // There is nowhere left to report a failing log file to.
+        let _ = file.write_all(line.as_bytes());
+    }
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::log::write($crate::log::Level::Error, format_args!($($arg)*))
+    };
+}
+
+// Named apart from the built-in `warn` attribute, which a plain `warn` would clash with.
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        $crate::log::write($crate::log::Level::Warn, format_args!($($arg)*))
+    };
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::log::write($crate::log::Level::Info, format_args!($($arg)*))
+    };
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Debug) {
+            $crate::log::write($crate::log::Level::Debug, format_args!($($arg)*))
+        }
+    };
+}
+
+pub(crate) use {debug, error, info, warning as warn};
@@ -0,0 +1,133 @@
+use std::{fs::File, io, path::Path, str::FromStr};
+
+use crate::{hash::read_full, open_files};
+
+/// Bytes read from the start of a file to tell its type, enough to reach the marker
+/// tar archives keep at offset 257.
+const SNIFF_SIZE: usize = 512;
+
+/// Broad kind of contents, told from the first bytes of a file rather than its name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FileKind {
+    Image,
+    Video,
+    Audio,
+    Document,
+    Archive,
+}
+
+impl FromStr for FileKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "image" => Ok(FileKind::Image),
+            "video" => Ok(FileKind::Video),
+            "audio" => Ok(FileKind::Audio),
+            "document" => Ok(FileKind::Document),
+            "archive" => Ok(FileKind::Archive),
+            _ => Err(format!(
+                "Unknown file type `{name}`, expected one of: image, video, audio, document, archive"
+            )),
+        }
+    }
+}
+
+/// Kind of the file at `path`, `None` when its first bytes match no known format.
+pub fn sniff(path: &Path) -> io::Result<Option<FileKind>> {
+    let _permit = open_files::acquire(1);
+    let mut file = File::open(path)?;
+    let mut header = [0; SNIFF_SIZE];
+    let length = read_full(&mut file, &mut header)?;
+    Ok(kind_of(&header[..length]))
+}
+
+/// Kind of contents starting with `header`.
+fn kind_of(header: &[u8]) -> Option<FileKind> {
+    let at = |offset: usize, magic: &[u8]| header.get(offset..offset + magic.len()) == Some(magic);
+
+    // Containers first, the format lives a few bytes in.
+    if at(0, b"RIFF") {
+        return match header.get(8..12)? {
+            b"WEBP" => Some(FileKind::Image),
+            b"AVI " => Some(FileKind::Video),
+            b"WAVE" => Some(FileKind::Audio),
+            _ => None,
+        };
+    }
+    if at(0, b"FORM") && (at(8, b"AIFF") || at(8, b"AIFC")) {
+        return Some(FileKind::Audio);
+    }
+    if at(4, b"ftyp") {
+        return match header.get(8..12)? {
+            b"heic" | b"heix" | b"mif1" | b"msf1" | b"avif" => Some(FileKind::Image),
+            b"M4A " | b"M4B " => Some(FileKind::Audio),
+            _ => Some(FileKind::Video),
+        };
+    }
+    if at(0, b"PK\x03\x04") {
+        // Office and e-book formats are zip files, their first entry gives them away.
+        let name = header.get(30..).unwrap_or_default();
+        let document = name.starts_with(b"[Content_Types].xml")
+            || name.starts_with(b"mimetypeapplication/vnd.oasis.opendocument")
+            || name.starts_with(b"mimetypeapplication/epub+zip");
+        return Some(if document {
+            FileKind::Document
+        } else {
+            FileKind::Archive
+        });
+    }
+
+    const IMAGES: &[&[u8]] = &[
+        b"\xFF\xD8\xFF",
+        b"\x89PNG\r\n\x1A\n",
+        b"GIF87a",
+        b"GIF89a",
+        b"BM",
+        b"II*\0",
+        b"MM\0*",
+        b"\0\0\x01\0",
+        b"8BPS",
+    ];
+    const VIDEOS: &[&[u8]] = &[
+        b"\x1A\x45\xDF\xA3",
+        b"FLV",
+        b"\0\0\x01\xBA",
+        b"\0\0\x01\xB3",
+        b"\x30\x26\xB2\x75\x8E\x66\xCF\x11",
+    ];
+    const AUDIO: &[&[u8]] = &[
+        b"ID3",
+        b"\xFF\xFB",
+        b"\xFF\xF3",
+        b"\xFF\xF2",
+        b"fLaC",
+        b"OggS",
+        b"MThd",
+        b"#!AMR",
+    ];
+    const DOCUMENTS: &[&[u8]] = &[b"%PDF", b"\xD0\xCF\x11\xE0\xA1\xB1\x1A\xE1", b"{\\rtf"];
+    const ARCHIVES: &[&[u8]] = &[
+        b"Rar!\x1A\x07",
+        b"7z\xBC\xAF\x27\x1C",
+        b"\x1F\x8B",
+        b"BZh",
+        b"\xFD7zXZ\0",
+        b"\x28\xB5\x2F\xFD",
+        b"\x04\x22\x4D\x18",
+        b"MSCF",
+    ];
+    let kinds = [
+        (FileKind::Image, IMAGES),
+        (FileKind::Video, VIDEOS),
+        (FileKind::Audio, AUDIO),
+        (FileKind::Document, DOCUMENTS),
+        (FileKind::Archive, ARCHIVES),
+    ];
+    for (kind, signatures) in kinds {
+        if signatures.iter().any(|magic| at(0, magic)) {
+            return Some(kind);
+        }
+    }
+    at(257, b"ustar").then_some(FileKind::Archive)
+}
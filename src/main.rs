@@ -1,136 +1,1478 @@
+mod actions;
+mod audio;
+mod bench;
+mod cache;
+mod checkpoint;
+mod config;
+mod devices;
+mod dirs;
+mod edit;
+mod exec;
+mod filter;
+mod hash;
+mod html;
+mod ignore_files;
+mod images;
+mod interrupt;
+mod journal;
+mod log;
+mod magic;
+mod manifest;
+mod media;
+mod open_files;
+mod platform;
+mod progress;
+mod report;
+mod spill;
+mod sqlite;
+mod text;
+mod units;
+#[cfg(target_os = "linux")]
+mod uring;
+mod verify;
+mod video;
+mod walk;
+
 use std::{
-    collections::{HashMap, VecDeque},
-    io::Read,
-    os::windows::fs::MetadataExt,
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap, HashSet},
+    hash::Hash,
     path::{Path, PathBuf},
+    sync::{
+        Mutex, PoisonError,
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
+use audio::AudioMode;
+use cache::HashCache;
+use checkpoint::Checkpoint;
 use clarg::{Arg, ArgMap, ArgParser};
-use sha2::{Digest, Sha256, digest::generic_array::functional::FunctionalSequence};
+use config::Config;
+use dirs::DirectoryGroup;
+use hash::{
+    FileHash, HashAlgorithm, get_file_hash, get_partial_hash, get_sampled_hash, get_tail_hash,
+};
+use images::ImageMode;
+use interrupt::StopReason;
+use magic::FileKind;
+use progress::{PROGRESS, Phase, ProgressDisplay};
+use text::TextMode;
+use units::format_size;
+use video::VideoMode;
+use walk::{FileEntry, walk};
+
+/// Number of leading bytes hashed to weed out same-size files before a full hash.
+const PARTIAL_HASH_SIZE: u64 = 16 * 1024;
+
+/// Size of each of the chunks sampled from a file by `--estimate`.
+const ESTIMATE_CHUNK_SIZE: u64 = 64 * 1024;
+
+/// Files found to hold the same content.
+struct DuplicateGroup {
+    hash: FileHash,
+    files: Vec<FileEntry>,
+}
 
-#[cfg(target_os = "windows")]
-const FILE_ATTRIBUTE_HIDDEN: u32 = 0x00000002;
+impl DuplicateGroup {
+    /// Sizes of the smallest and the largest copy. Copies compared by their contents
+    /// rather than their bytes may differ in size, every copy has the same otherwise.
+    fn size_range(&self) -> (u64, u64) {
+        let sizes = self.files.iter().map(|file| file.size);
+        (sizes.clone().min().unwrap_or(0), sizes.max().unwrap_or(0))
+    }
+
+    /// Space taken by every copy.
+    fn total_bytes(&self) -> u64 {
+        self.files.iter().map(|file| file.size).sum()
+    }
+
+    /// Space taken by every copy but one, freed by removing them. The largest copy is
+    /// counted as kept, so the space freed is never overstated.
+    fn wasted_bytes(&self) -> u64 {
+        self.total_bytes() - self.size_range().1
+    }
+}
+
+/// Group of four byte files at `paths`, for tests.
+#[cfg(test)]
+fn group_of(paths: &[&str]) -> DuplicateGroup {
+    DuplicateGroup {
+        hash: FileHash {
+            algorithm: HashAlgorithm::Blake3,
+            digest: "0".repeat(64),
+        },
+        files: paths
+            .iter()
+            .map(|path| FileEntry {
+                path: PathBuf::from(path),
+                size: 4,
+                modified: None,
+                file_id: None,
+                known_hash: None,
+            })
+            .collect(),
+    }
+}
+
+/// Outcome of a scan: how many files were looked at and the groups of duplicates found.
+struct ScanResult {
+    files_scanned: usize,
+    hardlinks_collapsed: usize,
+    /// Files left out because another process kept them locked.
+    files_locked: u64,
+    groups: Vec<DuplicateGroup>,
+    /// Directories found to be identical, with `--dirs`.
+    directory_groups: Vec<DirectoryGroup>,
+    /// Groups found but left out of `groups` by `--top`.
+    groups_omitted: usize,
+    /// Why the scan was cut short, if it was. `groups` only holds what was confirmed
+    /// until then.
+    stopped: Option<StopReason>,
+    /// Time spent finding files, and comparing them from then on.
+    walk_time: Duration,
+    hash_time: Duration,
+}
 
 fn main() {
+    let command: Vec<String> = std::env::args().skip(1).collect();
+    if command.first().is_some_and(|name| name == "bench") {
+        if let Err(err) = bench::run(&command[1..]) {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+        return;
+    }
+    if command.first().is_some_and(|name| name == "undo") {
+        if let Err(err) = journal::run(&command[1..]) {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let args = setup();
-    let file_hashmap = check_duplicates(args);
-    print_results(file_hashmap);
+    let config = match Config::from_args(&args) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    };
+    if let Err(err) = log::init(config.verbosity, config.log_file.as_deref()) {
+        eprintln!("Error opening log file: {err}");
+        std::process::exit(1);
+    }
+    if let Some(location) = &config.output_path
+        && let Err(err) = report::set_output(location)
+    {
+        eprintln!(
+            "Error opening output file: `{}` {err}",
+            location.to_string_lossy()
+        );
+        std::process::exit(1);
+    }
+    // Priorities are inherited by threads, so this must come before any of them start.
+    if config.nice
+        && let Err(err) = platform::lower_priority()
+    {
+        log::error!("Error lowering priority: {err}");
+    }
+    open_files::set_limit(config.max_open_files);
+    devices::set_profile(config.device_profile);
+    interrupt::install_handler();
+    if let Some(limit) = config.max_duration {
+        interrupt::stop_after(limit);
+    }
+    let progress = config.progress.then(ProgressDisplay::start);
+    let result = check_duplicates(&config);
+    // Whatever follows the scan runs to its end, only Ctrl+C stops it.
+    interrupt::end_time_limit();
+    if let Some(progress) = progress {
+        progress.finish();
+    }
+    let stopped = result.stopped;
+    let (walk_time, hash_time) = (result.walk_time, result.hash_time);
+    let report_start = Instant::now();
+    // A script takes the place of the report.
+    if config.script.is_none() {
+        report::print_results(&result, &config);
+    }
+    if let Some(location) = &config.report_path
+        && let Err(err) = html::write_report(location, &result, &config)
+    {
+        log::error!(
+            "Error writing report: `{}` {err}",
+            location.to_string_lossy()
+        );
+    }
+    if let Some(location) = &config.manifest_path
+        && let Err(err) = manifest::write_manifest(location, &result, &config)
+    {
+        log::error!(
+            "Error writing manifest: `{}` {err}",
+            location.to_string_lossy()
+        );
+    }
+    if let Some(location) = &config.sqlite_path
+        && let Err(err) = sqlite::export(location, &result, &config)
+    {
+        log::error!(
+            "Error exporting results: `{}` {err}",
+            location.to_string_lossy()
+        );
+    }
+    // Commands run on copies still in place, before any action.
+    if config.exec.is_some() && stopped != Some(StopReason::Interrupted) {
+        exec::run(&result.groups, &config);
+    }
+    // Copies are only removed from what a scan that ran its course found.
+    if config.action.is_some() && stopped != Some(StopReason::Interrupted) {
+        actions::apply(&result.groups, &config);
+    }
+    if config.stats {
+        print_stats(walk_time, hash_time, report_start.elapsed());
+    }
+    if stopped == Some(StopReason::Interrupted) {
+        std::process::exit(interrupt::INTERRUPTED_EXIT_CODE);
+    }
 }
 
 /// Execute the logic that searches for duplicate files.
-/// This function calculates a hash of each file. When duplicates are found,
-/// a list of files is stored per each hash.
-fn check_duplicates(args: ArgMap) -> HashMap<String, Vec<PathBuf>> {
-    let path = args.get_raw("path").expect("Invalid path");
-    let mut file_hashmap = HashMap::new();
-    let mut directory_queue = VecDeque::new();
-
-    // Visit the folder passed.
-    if let Err(err) = walk_directory(path, &mut directory_queue, &mut file_hashmap, &args) {
-        eprintln!("Error walking directory: `{path}` {err}");
-    } else {
-        // We may need to run recursively
-        if args.get::<bool>("recurse").is_ok() {
-            while !directory_queue.is_empty() {
-                let tip = directory_queue.pop_front();
-                if let Some(directory) = tip {
-                    if let Err(err) =
-                        walk_directory(&directory, &mut directory_queue, &mut file_hashmap, &args)
-                    {
-                        eprintln!(
-                            "Error walking directory: `{}` {err}",
-                            directory.to_string_lossy()
-                        );
-                    }
+/// Files are first grouped by size, then a hash is calculated for each file sharing
+/// its size with another. When duplicates are found, a list of files is stored per each hash.
+fn check_duplicates(config: &Config) -> ScanResult {
+    let path = &config.path;
+    let mut list_truncated = false;
+    let (checkpoint, directories, found) = match (&config.files_from, &config.checkpoint_path) {
+        (Some(source), _) => match walk::read_file_list(source, config) {
+            // The reference directory is still walked for the originals, those listed
+            // would be found twice.
+            Ok((mut files, truncated)) => {
+                list_truncated = truncated;
+                files.retain(|file| !config.in_reference(&file.path));
+                (None, config.reference.iter().cloned().collect(), files)
+            }
+            Err(err) => {
+                log::error!(
+                    "Error reading file list: `{}` {err}",
+                    source.to_string_lossy()
+                );
+                std::process::exit(1);
+            }
+        },
+        (None, Some(location)) if config.resume => {
+            match Checkpoint::resume(location.clone(), path.clone()) {
+                Ok((checkpoint, resumed)) => (Some(checkpoint), resumed.pending, resumed.files),
+                Err(err) => {
+                    log::error!("{err}");
+                    std::process::exit(1);
                 }
             }
         }
+        (None, location) => (
+            location
+                .clone()
+                .map(|location| Checkpoint::new(location, path.clone())),
+            walk::distinct_roots(&config.roots),
+            Vec::new(),
+        ),
+    };
+    let walk_start = Instant::now();
+    let (files, truncated) = walk(directories, found, config, checkpoint.as_ref());
+    let truncated = truncated || list_truncated;
+    let walk_time = walk_start.elapsed();
+    let hash_start = Instant::now();
+    let files_scanned = files.len();
+    log::info!(
+        "Found {files_scanned} files in {:.2}s",
+        walk_time.as_secs_f64()
+    );
+
+    // Incremental scans start out from the hashes recorded by the previous scan of this path.
+    let state = config.incremental.then(|| {
+        let location = cache::scan_state_path(path);
+        if location.is_none() {
+            log::warn!("No cache directory available, running a full scan instead.");
+        }
+        location.map(HashCache::load)
+    });
+    let state = state.flatten().map(Mutex::new);
+    let mut scanned_paths: HashSet<PathBuf> = HashSet::new();
+
+    // Streamed groups are verified and printed one at a time, as soon as they are confirmed.
+    let mut streamed = Vec::new();
+    let mut on_confirmed = |hash: &FileHash, files: &[FileEntry]| {
+        if config.stream {
+            let group = DuplicateGroup {
+                hash: hash.clone(),
+                files: files.to_vec(),
+            };
+            let confirmed = match config.verify {
+                true => verify::verify_group(group, config.buffer_size),
+                false => vec![group],
+            };
+            for group in confirmed
+                .into_iter()
+                .filter(|group| reportable(config, &group.files))
+            {
+                report::print_streamed(&group, config);
+                streamed.push(group);
+            }
+        }
+    };
+
+    // Size groups are compared in batches fitting the memory limit, all at once without one.
+    let mut groups = Vec::new();
+    let mut hardlinks_collapsed = 0;
+    let mut batch = Vec::new();
+    let mut batch_size = 0;
+    // Images, audio, video or text compared by what they show, sound like or say,
+    // whatever their size.
+    let mut media_files = Vec::new();
+    for mut files in files.into_size_groups() {
+        if interrupt::interrupted() {
+            break;
+        }
+        hardlinks_collapsed += collapse_hardlinks(&mut files);
+        if state.is_some() {
+            scanned_paths.extend(files.iter().map(|file| file.path.clone()));
+        }
+        if config.images.is_some()
+            || config.audio.is_some()
+            || config.video.is_some()
+            || config.text.is_some()
+        {
+            media_files.extend(files);
+            continue;
+        }
+        // Only sizes shared by two or more files can hold duplicates, every other file
+        // is dropped here without ever being read.
+        if files.len() < 2 {
+            continue;
+        }
+        // A group can only span directories the files of its size do.
+        if !reportable(config, &files) {
+            continue;
+        }
+        if let Some(state) = &state {
+            let state = state.lock().unwrap_or_else(PoisonError::into_inner);
+            for file in &mut files {
+                file.known_hash = state.get(&file.path, file.size, file.modified, config.algorithm);
+            }
+        }
+        // Hashes the resumed run got to are as good.
+        if let Some(checkpoint) = &checkpoint {
+            for file in files.iter_mut().filter(|file| file.known_hash.is_none()) {
+                file.known_hash = checkpoint
+                    .known_hash(file)
+                    .filter(|hash| hash.algorithm == config.algorithm);
+            }
+        }
+
+        // Looking for copies within a directory, files elsewhere can not be one.
+        let buckets = match config.same_dir {
+            true => split_by_directory(files),
+            false => vec![files],
+        };
+        for files in buckets {
+            batch_size += files.iter().map(spill::estimated_size).sum::<usize>();
+            batch.push(files);
+        }
+        if config
+            .memory_limit
+            .is_some_and(|limit| batch_size as u64 >= limit)
+        {
+            let batch = std::mem::take(&mut batch);
+            batch_size = 0;
+            groups.extend(find_duplicates(
+                batch,
+                config,
+                state.as_ref(),
+                checkpoint.as_ref(),
+                &mut on_confirmed,
+            ));
+        }
+    }
+    groups.extend(find_duplicates(
+        batch,
+        config,
+        state.as_ref(),
+        checkpoint.as_ref(),
+        &mut on_confirmed,
+    ));
+    // Files of any size may match, but only those of the type asked for.
+    if let Some(kind) = config.file_type {
+        media_files = of_kind(vec![media_files], kind, config).concat();
+    }
+    match (config.images, config.audio, config.video, config.text) {
+        (Some(ImageMode::Similar), ..) => groups.extend(images::find_similar(media_files, config)),
+        (Some(ImageMode::Pixels), ..) => {
+            groups.extend(group_by_content(media_files, config, images::pixel_hash));
+        }
+        (_, Some(AudioMode::Similar), ..) => {
+            groups.extend(audio::find_similar(media_files, config));
+        }
+        (_, Some(AudioMode::Frames), ..) => {
+            groups.extend(group_by_content(media_files, config, audio::frame_hash));
+        }
+        (_, _, Some(VideoMode::Similar), _) => {
+            groups.extend(video::find_similar(media_files, config));
+        }
+        (.., Some(TextMode::Similar)) => groups.extend(text::find_similar(media_files, config)),
+        (None, None, None, None) => {}
+    }
+    // A completed scan has nothing left to resume.
+    if let Some(checkpoint) = checkpoint {
+        match interrupt::interrupted() {
+            true => checkpoint.flush(),
+            false => checkpoint.remove(),
+        }
+    }
+
+    if let Some(state) = state {
+        let mut state = state.into_inner().unwrap_or_else(PoisonError::into_inner);
+        for group in &groups {
+            for file in &group.files {
+                state.insert(file.path.clone(), file.size, file.modified, &group.hash);
+            }
+        }
+        // Files gone since the previous scan must not linger in the state. A walk cut
+        // short did not see every file, so nothing can be told to be gone.
+        if !interrupt::interrupted() && !truncated {
+            state.retain(|path| scanned_paths.contains(path));
+        }
+        if let Err(err) = state.save() {
+            log::error!("Error saving incremental scan state: {err}");
+        }
+    }
+
+    if config.stream {
+        groups = streamed;
+    }
+    if !config.stream && config.verify {
+        let total_bytes = groups.iter().map(DuplicateGroup::total_bytes).sum();
+        PROGRESS.start_phase(Phase::Verifying, total_bytes);
+        groups = verify::verify_groups(groups, config.buffer_size, config.threads);
+    }
+    // Directories missing some of their files could look identical when they are not.
+    let directory_groups = match config.dirs && !interrupt::interrupted() && !truncated {
+        true => dirs::find_duplicate_dirs(&groups, &config.roots),
+        false => Vec::new(),
+    };
+    let mut groups_omitted = 0;
+    if !config.stream {
+        groups.retain(|group| reportable(config, &group.files));
+        if let Some(top) = config.top {
+            groups_omitted = report::keep_top(&mut groups, top);
+        }
+        // Groups come out of hashing in whatever order they completed.
+        report::sort_groups(&mut groups, config.sort, config.reverse);
+    }
+    log::info!(
+        "Compared files in {:.2}s, {} groups of duplicates found",
+        hash_start.elapsed().as_secs_f64(),
+        groups.len()
+    );
+    ScanResult {
+        files_scanned,
+        hardlinks_collapsed,
+        files_locked: PROGRESS.files_locked.load(Ordering::Relaxed),
+        groups,
+        directory_groups,
+        groups_omitted,
+        stopped: interrupt::stop_reason().or(truncated.then_some(StopReason::MaxFiles)),
+        walk_time,
+        hash_time: hash_start.elapsed(),
     }
-    file_hashmap
 }
 
-/// Prints the results of the execution including all duplicates found if any.
-fn print_results(file_hashmap: HashMap<String, Vec<PathBuf>>) {
-    let mut duplicates_found = false;
-    println!("Went through: {} unique files", file_hashmap.len());
+/// Narrow groups of files sharing their size down to groups of duplicates.
+/// Files are compared by the hash of their first bytes, of their last bytes when
+/// `--tail-check` is set, and finally by a full hash. Every hash computed along the
+/// way is recorded into `state` when running incrementally. Groups are passed to
+/// `on_confirmed` as soon as they are known, while the rest of the files are still
+/// being hashed. Full hashes are also appended to `checkpoint`, so an interrupted scan
+/// does not have to compute them again.
+fn find_duplicates(
+    size_groups: Vec<Vec<FileEntry>>,
+    config: &Config,
+    state: Option<&Mutex<HashCache>>,
+    checkpoint: Option<&Checkpoint>,
+    on_confirmed: &mut dyn FnMut(&FileHash, &[FileEntry]),
+) -> Vec<DuplicateGroup> {
+    // Only files sharing their size with another get opened to tell their type.
+    let size_groups = match config.file_type {
+        Some(kind) => of_kind(size_groups, kind, config),
+        None => size_groups,
+    };
+
+    // Files hashed by a previous scan can only be compared by full hash, so groups
+    // holding any of them skip the prefix pass.
+    let (known_groups, size_groups): (Vec<_>, Vec<_>) = size_groups
+        .into_iter()
+        .partition(|files| files.iter().any(|file| file.known_hash.is_some()));
 
-    for (_, file_list) in file_hashmap {
-        if file_list.len() > 1 {
-            duplicates_found = true;
-            println!("------- Multiple Entries Found -------");
-            for (index, file) in file_list.iter().enumerate() {
-                println!("{:>5} -> `{}`", index + 1, file.to_string_lossy());
+    // Cheap pass over the first bytes of each candidate, only colliding prefixes get fully read.
+    PROGRESS.start_phase(
+        Phase::Prefix,
+        size_groups
+            .iter()
+            .flatten()
+            .map(|file| file.size.min(PARTIAL_HASH_SIZE))
+            .sum(),
+    );
+    let prefix_groups = refine_groups(
+        size_groups,
+        config.threads,
+        config.retry_locked,
+        |file| {
+            get_partial_hash(
+                &file.path,
+                config.algorithm,
+                PARTIAL_HASH_SIZE,
+                config.buffer_size,
+            )
+        },
+        |hash, files| {
+            if files[0].size <= PARTIAL_HASH_SIZE {
+                on_confirmed(hash, files);
             }
-            println!("--------------------------------------");
+        },
+    );
+    // Files no larger than the prefix were read whole already, their prefix hash is final.
+    let (mut confirmed, partial): (Vec<_>, Vec<_>) = prefix_groups
+        .into_iter()
+        .partition(|(_, files)| files[0].size <= PARTIAL_HASH_SIZE);
+    let mut full_groups: Vec<_> = partial.into_iter().map(|(_, files)| files).collect();
+
+    // Files often differ only near their end, comparing that first can spare full reads.
+    if let Some(tail_size) = config.tail_check {
+        PROGRESS.start_phase(
+            Phase::Tail,
+            full_groups
+                .iter()
+                .flatten()
+                .map(|file| file.size.min(tail_size))
+                .sum(),
+        );
+        full_groups = refine_groups(
+            full_groups,
+            config.threads,
+            config.retry_locked,
+            |file| get_tail_hash(&file.path, config.algorithm, tail_size, config.buffer_size),
+            |_, _| {},
+        )
+        .into_iter()
+        .map(|(_, files)| files)
+        .collect();
+    }
+    full_groups.extend(known_groups);
+    // Largest candidates first, the biggest savings get confirmed early on.
+    full_groups.sort_by_key(|files| Reverse(files[0].size));
+
+    // Estimates only look at a few chunks of each file, nothing they find is worth keeping.
+    if config.estimate {
+        PROGRESS.start_phase(
+            Phase::Hashing,
+            full_groups
+                .iter()
+                .flatten()
+                .map(|file| file.size.min(3 * ESTIMATE_CHUNK_SIZE))
+                .sum(),
+        );
+        confirmed.extend(refine_groups(
+            full_groups,
+            config.threads,
+            config.retry_locked,
+            |file| {
+                get_sampled_hash(
+                    &file.path,
+                    config.algorithm,
+                    ESTIMATE_CHUNK_SIZE,
+                    config.buffer_size,
+                )
+            },
+            on_confirmed,
+        ));
+        return confirmed
+            .into_iter()
+            .map(|(hash, files)| DuplicateGroup { hash, files })
+            .collect();
+    }
+
+    PROGRESS.start_phase(
+        Phase::Hashing,
+        full_groups.iter().flatten().map(|file| file.size).sum(),
+    );
+
+    let cache = config
+        .cache_path
+        .clone()
+        .map(|location| Mutex::new(HashCache::load(location)));
+    let full_hash = |file: &FileEntry| {
+        if let Some(hash) = &file.known_hash {
+            PROGRESS.skip_bytes(file.size);
+            return Ok(hash.clone());
+        }
+        let hash = cached_file_hash(file, config, cache.as_ref())?;
+        if let Some(state) = state {
+            state.lock().unwrap_or_else(PoisonError::into_inner).insert(
+                file.path.clone(),
+                file.size,
+                file.modified,
+                &hash,
+            );
+        }
+        if let Some(checkpoint) = checkpoint {
+            checkpoint.record_hash(file, &hash);
+        }
+        Ok(hash)
+    };
+    confirmed.extend(refine_groups(
+        full_groups,
+        config.threads,
+        config.retry_locked,
+        full_hash,
+        on_confirmed,
+    ));
+    if let Some(cache) = cache {
+        let cache = cache.into_inner().unwrap_or_else(PoisonError::into_inner);
+        if let Err(err) = cache.save() {
+            log::error!("Error saving hash cache: {err}");
         }
     }
+    confirmed
+        .into_iter()
+        .map(|(hash, files)| DuplicateGroup { hash, files })
+        .collect()
+}
 
-    if !duplicates_found {
-        println!("No duplicates found with hash comparison method.");
+/// Full hash of a file, reusing the persistent cache when the file did not change since
+/// it was last hashed.
+fn cached_file_hash(
+    file: &FileEntry,
+    config: &Config,
+    cache: Option<&Mutex<HashCache>>,
+) -> std::io::Result<FileHash> {
+    let Some(cache) = cache else {
+        return get_file_hash(&file.path, config.algorithm, config.read_options());
+    };
+    let canonical_path = std::fs::canonicalize(&file.path)?;
+    let cached = cache.lock().unwrap_or_else(PoisonError::into_inner).get(
+        &canonical_path,
+        file.size,
+        file.modified,
+        config.algorithm,
+    );
+    if let Some(hash) = cached {
+        PROGRESS.skip_bytes(file.size);
+        return Ok(hash);
     }
+
+    let hash = get_file_hash(&file.path, config.algorithm, config.read_options())?;
+    cache.lock().unwrap_or_else(PoisonError::into_inner).insert(
+        canonical_path,
+        file.size,
+        file.modified,
+        &hash,
+    );
+    Ok(hash)
 }
 
-/// Set up, and parse arguments for the CLI.
-fn setup() -> ArgMap {
-    ArgParser::new("Find duplicate files.")
-        .arg(Arg::string(
-            "path",
-            Some('p'),
-            true,
-            "Directory being analyzed",
-        ))
-        .arg(Arg::boolean("recurse", Some('r'), "Run recursively"))
-        .arg(Arg::boolean("include-hidden", None, "Include hidden."))
-        .parse()
+/// Keep a single path for files hardlinked together.
+/// Hardlinks share their data on disk, removing one of them frees no space, so they
+/// are never reported as duplicates of each other. Returns how many paths were dropped.
+fn collapse_hardlinks(files: &mut Vec<FileEntry>) -> usize {
+    let count = files.len();
+    // A file alone in its size group has no hardlink to share it with here.
+    if count < 2 {
+        return 0;
+    }
+    files.iter_mut().for_each(FileEntry::identify);
+    let mut seen = HashSet::new();
+    files.retain(|file| file.file_id.is_none_or(|id| seen.insert(id)));
+    count - files.len()
 }
 
-/// Walk a given directory.
-/// # Arguments
-/// `path` the directory being analyzed
-/// `to_visit_queue` queue to store all directories found. Used in recursive execution.
-/// `file_hash_map`  map storing all hashes and files analyzed.
-/// `config` map or arguments passed to the CLI
-fn walk_directory(
-    path: impl AsRef<Path>,
-    to_visit_queue: &mut VecDeque<PathBuf>,
-    file_hash_map: &mut HashMap<String, Vec<PathBuf>>,
-    config: &ArgMap,
-) -> std::io::Result<()> {
-    let directory_iterator = std::fs::read_dir(path)?;
-    let include_hidden = config.has_arg("include-hidden");
-    for dir_item in directory_iterator.flatten() {
-        let item_path = dir_item.path();
-
-        // Check if hidden files are to be ignored
-        if let Ok(meta) = item_path.metadata() {
-            if meta.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0 && !include_hidden {
-                continue;
-            }
+/// Whether a group made of `files` is to be reported. With `--reference` it must hold
+/// files from both in and out of the reference directory. With `--cross-dir` its files
+/// must live in at least two directories, or under two paths when scanning several.
+fn reportable(config: &Config, files: &[FileEntry]) -> bool {
+    if config.reference.is_some() {
+        let originals = files
+            .iter()
+            .filter(|file| config.in_reference(&file.path))
+            .count();
+        if originals == 0 || originals == files.len() {
+            return false;
         }
+    }
+    if !config.cross_dir {
+        return true;
+    }
+    let first = group_location(&files[0], &config.roots);
+    files
+        .iter()
+        .any(|file| group_location(file, &config.roots) != first)
+}
 
-        if item_path.is_dir() {
-            to_visit_queue.push_back(item_path);
-        } else {
-            let hash = get_file_hash(&item_path)?;
-            let file_list = file_hash_map.entry(hash).or_insert(Vec::new());
-            file_list.push(item_path);
-        }
+/// Where `file` lives as far as `--cross-dir` is concerned: its directory, or the path
+/// it was found under when scanning several.
+fn group_location<'a>(file: &'a FileEntry, roots: &'a [PathBuf]) -> Option<&'a Path> {
+    match roots.len() {
+        1 => file.path.parent(),
+        _ => walk::root_of(&file.path, roots),
     }
+}
 
-    Ok(())
+/// The files of `groups` whose contents are of `kind`, still grouped, those left alone
+/// in their group dropped.
+fn of_kind(groups: Vec<Vec<FileEntry>>, kind: FileKind, config: &Config) -> Vec<Vec<FileEntry>> {
+    refine_groups(
+        groups,
+        config.threads,
+        config.retry_locked,
+        |file| magic::sniff(&file.path),
+        |_, _| {},
+    )
+    .into_iter()
+    .filter(|(found, _)| *found == Some(kind))
+    .map(|(_, files)| files)
+    .collect()
 }
 
-/// Determine the hash for a given file
-fn get_file_hash(path: &PathBuf) -> std::io::Result<String> {
-    let mut buffer = [0; 4096];
-    let mut file = std::fs::File::open(path)?;
-    let mut hasher = Sha256::new();
-    loop {
-        let read_bytes = file.read(&mut buffer)?;
-        if read_bytes == 0 {
-            break;
-        }
-        hasher.update(&buffer[..read_bytes]);
+/// Groups of `files` whose contents hash the same by `content_hash`, such as the pixels
+/// of images or the audio frames of songs. Files it gives no hash for are left out.
+fn group_by_content(
+    files: Vec<FileEntry>,
+    config: &Config,
+    content_hash: fn(&Path, HashAlgorithm) -> std::io::Result<Option<FileHash>>,
+) -> Vec<DuplicateGroup> {
+    PROGRESS.start_phase(Phase::Hashing, files.iter().map(|file| file.size).sum());
+    let key_of = |file: &FileEntry| {
+        let hash = content_hash(&file.path, config.algorithm);
+        PROGRESS.add_bytes(file.size);
+        hash
+    };
+    // Files it gives no hash for all share the `None` key, they are no group.
+    refine_groups(
+        vec![files],
+        config.threads,
+        config.retry_locked,
+        key_of,
+        |_, _| {},
+    )
+    .into_iter()
+    .filter_map(|(hash, files)| Some(DuplicateGroup { hash: hash?, files }))
+    .collect()
+}
+
+/// Split files of the same size by the directory holding them, dropping files left
+/// alone in theirs.
+fn split_by_directory(files: Vec<FileEntry>) -> Vec<Vec<FileEntry>> {
+    let mut by_directory: BTreeMap<PathBuf, Vec<FileEntry>> = BTreeMap::new();
+    for file in files {
+        let directory = file
+            .path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        by_directory.entry(directory).or_default().push(file);
     }
-    Ok(hasher.finalize().map(|byte| format!("{:x}", byte)).join(""))
+    by_directory
+        .into_values()
+        .filter(|files| files.len() > 1)
+        .collect()
+}
+
+/// Split every group into smaller groups of files sharing the same key.
+/// Keys are computed by `key_of` on a pool of `threads` worker threads. Workers pull files from
+/// a shared index and send results back over a channel, so only this thread ever
+/// touches the groups. Files left alone in their group are dropped, they can not
+/// have a duplicate anymore. Each smaller group is handed to `on_refined` as soon
+/// as every file of the group it came from has its key. Workers stop picking up files
+/// once the scan is interrupted, groups left incomplete are not returned.
+/// Files locked by another process are left out, or tried once more after every other
+/// file when `retry_locked` is set.
+fn refine_groups<K, F>(
+    groups: Vec<Vec<FileEntry>>,
+    threads: usize,
+    retry_locked: bool,
+    key_of: F,
+    mut on_refined: impl FnMut(&K, &[FileEntry]),
+) -> Vec<(K, Vec<FileEntry>)>
+where
+    K: Eq + Hash + Clone + Send,
+    F: Fn(&FileEntry) -> std::io::Result<K> + Sync,
+{
+    let tasks: Vec<(usize, usize)> = groups
+        .iter()
+        .enumerate()
+        .flat_map(|(group, files)| (0..files.len()).map(move |file| (group, file)))
+        .collect();
+    let mut keys: Vec<Vec<Option<K>>> =
+        groups.iter().map(|files| vec![None; files.len()]).collect();
+    let mut remaining: Vec<usize> = groups.iter().map(Vec::len).collect();
+    let mut refined = Vec::new();
+    let worker_count = threads.min(tasks.len()).max(1);
+    let next_task = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..worker_count {
+            let sender = sender.clone();
+            let (groups, tasks, next_task, key_of) = (&groups, &tasks, &next_task, &key_of);
+            scope.spawn(move || {
+                while let Some(&(group, file)) = tasks
+                    .get(next_task.fetch_add(1, Ordering::Relaxed))
+                    .filter(|_| !interrupt::interrupted())
+                {
+                    let entry = &groups[group][file];
+                    let key = {
+                        let _permit = devices::acquire(entry.file_id.map(|(device, _)| device));
+                        key_of(entry)
+                    };
+                    PROGRESS.files_hashed.fetch_add(1, Ordering::Relaxed);
+                    if sender.send((group, file, key)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        // Only the workers hold senders now, the loop ends once all of them finish.
+        drop(sender);
+
+        let mut settle = |group: usize, file: usize, result: std::io::Result<K>| {
+            match result {
+                Ok(key) => keys[group][file] = Some(key),
+                // Files cut short by an interruption are not worth a message each.
+                Err(_) if interrupt::interrupted() => {}
+                Err(err) if platform::is_sharing_violation(&err) => {
+                    PROGRESS.files_locked.fetch_add(1, Ordering::Relaxed);
+                    log::info!(
+                        "Skipping locked file: `{}`",
+                        groups[group][file].path.to_string_lossy()
+                    );
+                }
+                Err(err) => log::error!(
+                    "Error hashing file: `{}` {err}",
+                    groups[group][file].path.to_string_lossy()
+                ),
+            }
+
+            // Split the group as soon as all its keys are in, instead of waiting on the others.
+            remaining[group] -= 1;
+            if remaining[group] == 0 {
+                let mut by_key: HashMap<K, Vec<FileEntry>> = HashMap::new();
+                for (entry, key) in groups[group].iter().zip(std::mem::take(&mut keys[group])) {
+                    if let Some(key) = key {
+                        by_key.entry(key).or_default().push(entry.clone());
+                    }
+                }
+                for (key, files) in by_key {
+                    if files.len() > 1 {
+                        on_refined(&key, &files);
+                        refined.push((key, files));
+                    }
+                }
+            }
+        };
+
+        let mut locked = Vec::new();
+        for (group, file, result) in receiver {
+            match result {
+                Err(err) if retry_locked && platform::is_sharing_violation(&err) => {
+                    locked.push((group, file));
+                }
+                result => settle(group, file, result),
+            }
+        }
+        // The other process may be done with them by now.
+        for (group, file) in locked {
+            if interrupt::interrupted() {
+                break;
+            }
+            let entry = &groups[group][file];
+            let key = {
+                let _permit = devices::acquire(entry.file_id.map(|(device, _)| device));
+                key_of(entry)
+            };
+            settle(group, file, key);
+        }
+    });
+    refined
+}
+
+/// Prints how much work the scan did and where the time went, on stderr so it never
+/// mixes with the report.
+fn print_stats(walk_time: Duration, hash_time: Duration, report_time: Duration) {
+    let bytes_hashed = PROGRESS.bytes_hashed.load(Ordering::Relaxed);
+    let throughput = match hash_time.as_secs_f64() {
+        0.0 => 0,
+        seconds => (bytes_hashed as f64 / seconds) as u64,
+    };
+    eprintln!("------------- Statistics -------------");
+    eprintln!(
+        "Directories read:   {}",
+        PROGRESS.directories.load(Ordering::Relaxed)
+    );
+    eprintln!(
+        "Files enumerated:   {}",
+        PROGRESS.files_found.load(Ordering::Relaxed)
+    );
+    eprintln!(
+        "Hashes computed:    {}",
+        PROGRESS.files_hashed.load(Ordering::Relaxed)
+    );
+    eprintln!("Bytes read:         {}", format_size(bytes_hashed));
+    eprintln!("Throughput:         {}/s", format_size(throughput));
+    eprintln!("Traversal time:     {:.2}s", walk_time.as_secs_f64());
+    eprintln!("Hashing time:       {:.2}s", hash_time.as_secs_f64());
+    eprintln!("Reporting time:     {:.2}s", report_time.as_secs_f64());
+    eprintln!("--------------------------------------");
+}
+
+/// Set up, and parse arguments for the CLI.
+fn setup() -> ArgMap {
+    ArgParser::new("Find duplicate files.")
+        .arg(Arg::strings(
+            "path",
+            Some('p'),
+            false,
+            "Directory being analyzed. Repeat it to compare several directories together.",
+        ))
+        .arg(Arg::boolean("recurse", Some('r'), "Run recursively"))
+        .arg(Arg::string(
+            "max-depth",
+            None,
+            false,
+            "With --recurse, only walk this many levels of directories below the path.",
+        ))
+        .arg(Arg::boolean("include-hidden", None, "Include hidden."))
+        .arg(Arg::boolean(
+            "one-file-system",
+            Some('x'),
+            "Stay on the file system of the path, never walking into mount points.",
+        ))
+        .arg(Arg::boolean(
+            "follow-symlinks",
+            None,
+            "Follow symlinks and junctions to files and directories, each directory is still walked once.",
+        ))
+        .arg(Arg::boolean(
+            "warn-special",
+            None,
+            "Warn about devices, sockets and FIFOs found, which are never compared.",
+        ))
+        .arg(Arg::boolean(
+            "skip-bundles",
+            None,
+            "Treat macOS bundles such as .app and .photoslibrary as single items, never walking inside.",
+        ))
+        .arg(Arg::string(
+            "ext",
+            None,
+            false,
+            "Only compare files with these extensions, e.g. jpg,png.",
+        ))
+        .arg(Arg::string(
+            "exclude-ext",
+            None,
+            false,
+            "Skip files with these extensions, e.g. tmp,log.",
+        ))
+        .arg(Arg::string(
+            "include",
+            None,
+            false,
+            "Only compare files matching this pattern, relative to the path, e.g. '**/*.iso'.",
+        ))
+        .arg(Arg::string(
+            "exclude",
+            None,
+            false,
+            "Skip files and directories matching this pattern, relative to the path, e.g. '**/cache/**'.",
+        ))
+        .arg(Arg::boolean(
+            "respect-gitignore",
+            None,
+            "Skip files and directories ignored by git, through .gitignore and global ignore files.",
+        ))
+        .arg(Arg::boolean(
+            "no-fdupignore",
+            None,
+            "Do not read the .fdupignore files found while walking.",
+        ))
+        .arg(Arg::strings(
+            "exclude-dir",
+            None,
+            false,
+            "Never walk into this directory, by name or path, e.g. node_modules or /mnt/backup. Repeat it to skip several.",
+        ))
+        .arg(Arg::string(
+            "match",
+            None,
+            false,
+            "Only compare files whose name matches this regular expression.",
+        ))
+        .arg(Arg::string(
+            "not-match",
+            None,
+            false,
+            "Skip files whose name matches this regular expression.",
+        ))
+        .arg(Arg::boolean(
+            "include-empty",
+            None,
+            "Also report empty files, which are all duplicates of each other.",
+        ))
+        .arg(Arg::string(
+            "older-than",
+            None,
+            false,
+            "Only compare files last modified before this, a date like 2024-05-31 or a duration like 30d.",
+        ))
+        .arg(Arg::string(
+            "newer-than",
+            None,
+            false,
+            "Only compare files last modified after this, a date like 2024-05-31 or a duration like 30d.",
+        ))
+        .arg(Arg::string(
+            "owner",
+            None,
+            false,
+            "Only compare files owned by this user, by name or id (Unix only).",
+        ))
+        .arg(Arg::string(
+            "group",
+            None,
+            false,
+            "Only compare files owned by this group, by name or id (Unix only).",
+        ))
+        .arg(Arg::string(
+            "type",
+            None,
+            false,
+            "Only compare files of this type, told from their contents: image, video, audio, document or archive.",
+        ))
+        .arg(Arg::string(
+            "min-size",
+            None,
+            false,
+            "Skip files smaller than this, e.g. 4K.",
+        ))
+        .arg(Arg::string(
+            "max-size",
+            None,
+            false,
+            "Skip files larger than this, e.g. 2G.",
+        ))
+        .arg(Arg::boolean(
+            "no-mmap",
+            None,
+            "Never memory map large files while hashing.",
+        ))
+        .arg(Arg::boolean(
+            "io-uring",
+            None,
+            "Read files through io_uring, keeping several reads in flight (Linux only).",
+        ))
+        .arg(Arg::string(
+            "buffer-size",
+            None,
+            false,
+            "Bytes read at once, e.g. 256K or 4M. Defaults to `auto`, sized by file and disk type.",
+        ))
+        .arg(Arg::string(
+            "algorithm",
+            Some('a'),
+            false,
+            "Hash algorithm: sha256 (default), sha512, blake3, xxh3 or md5.",
+        ))
+        .arg(Arg::string(
+            "tail-check",
+            None,
+            false,
+            "Compare the last bytes of candidates, e.g. 64K, before hashing them in full.",
+        ))
+        .arg(Arg::boolean(
+            "no-cache",
+            None,
+            "Do not read or update the persistent hash cache.",
+        ))
+        .arg(Arg::string(
+            "cache-file",
+            None,
+            false,
+            "Location of the hash cache, defaults to the platform cache directory.",
+        ))
+        .arg(Arg::boolean(
+            "estimate",
+            None,
+            "Quick estimate comparing a few chunks of each file instead of its whole contents.",
+        ))
+        .arg(Arg::string(
+            "images",
+            None,
+            false,
+            "Compare images by what they show instead of their bytes: similar groups photos that look alike, even resized, re-encoded or slightly edited, pixels those with the same pixels whatever metadata they hold. Other files are left out.",
+        ))
+        .arg(Arg::string(
+            "image-distance",
+            None,
+            false,
+            "With --images similar, how many of the 64 bits of their perceptual hashes two images may differ by. Defaults to 10, lower is stricter.",
+        ))
+        .arg(Arg::string(
+            "audio",
+            None,
+            false,
+            "Compare audio files by what they sound like instead of their bytes: similar groups the same song in different formats or bitrates, told by its acoustic fingerprint, frames those holding the same audio frames whatever tags they hold. Other files are left out.",
+        ))
+        .arg(Arg::string(
+            "video",
+            None,
+            false,
+            "Compare videos by what they show instead of their bytes: similar groups the same clip in different containers or resolutions, told by frames sampled along it. Needs ffmpeg installed. Other files are left out.",
+        ))
+        .arg(Arg::string(
+            "text",
+            None,
+            false,
+            "Compare text files by what they say instead of their bytes: similar groups near duplicates such as revised copies of a document, told by the runs of words they share. Other files are left out.",
+        ))
+        .arg(Arg::string(
+            "text-similarity",
+            None,
+            false,
+            "With --text similar, the percentage of their runs of words two documents need in common. Defaults to 90, higher is stricter.",
+        ))
+        .arg(Arg::boolean(
+            "same-dir",
+            None,
+            "Only report duplicates living in the same directory, like `file (1).jpg` copies.",
+        ))
+        .arg(Arg::boolean(
+            "cross-dir",
+            None,
+            "Only report duplicates spread over several directories, leaving copies within one alone.",
+        ))
+        .arg(Arg::boolean(
+            "dirs",
+            None,
+            "Also report directories whose files and subdirectories are all identical, so a whole redundant copy can be removed at once.",
+        ))
+        .arg(Arg::string(
+            "files-from",
+            None,
+            false,
+            "Compare the files listed in this file, '-' for stdin, instead of walking --path. Paths are separated by line breaks, or NUL characters as written by `find -print0`.",
+        ))
+        .arg(Arg::string(
+            "reference",
+            None,
+            false,
+            "Directory holding the originals, scanned along with the path. Only files of the path with a copy in it are reported, and nothing in it is ever deleted, linked or moved.",
+        ))
+        .arg(Arg::string(
+            "output",
+            Some('o'),
+            false,
+            "Write the results to this file instead of stdout, in any format.",
+        ))
+        .arg(Arg::boolean(
+            "relative",
+            None,
+            "Report paths relative to the scan path, so reports stay valid wherever the tree is mounted.",
+        ))
+        .arg(Arg::string(
+            "format",
+            None,
+            false,
+            "Output format: text (default), json, csv, fdupes, markdown, or jsonl with one group per line as it is found.",
+        ))
+        .arg(Arg::boolean(
+            "quiet",
+            Some('q'),
+            "Only print the totals: groups, extra copies and reclaimable space.",
+        ))
+        .arg(Arg::boolean(
+            "no-summary",
+            None,
+            "Only print the groups of duplicates, without the totals.",
+        ))
+        .arg(Arg::string(
+            "sort",
+            None,
+            false,
+            "Order of the groups: size (default), count, wasted, or path.",
+        ))
+        .arg(Arg::boolean(
+            "reverse",
+            None,
+            "Print the groups in the opposite order: smallest first, or paths from Z to A.",
+        ))
+        .arg(Arg::string(
+            "top",
+            None,
+            false,
+            "Only report this many groups, those with the most reclaimable space.",
+        ))
+        .arg(Arg::string(
+            "color",
+            None,
+            false,
+            "Color the text output: auto (default, on a terminal unless NO_COLOR is set), always or never.",
+        ))
+        .arg(Arg::boolean(
+            "show-size",
+            None,
+            "With --format fdupes, start each group with the size of its files like fdupes -S.",
+        ))
+        .arg(Arg::string(
+            "report",
+            None,
+            false,
+            "Also write the results to this file as an HTML page with a sortable table.",
+        ))
+        .arg(Arg::string(
+            "export-sqlite",
+            None,
+            false,
+            "Also write the files, hashes and groups found to this SQLite database.",
+        ))
+        .arg(Arg::string(
+            "write-manifest",
+            None,
+            false,
+            "Also write the hash and path of every duplicate to this file, checkable with sha256sum -c.",
+        ))
+        .arg(Arg::string(
+            "template",
+            None,
+            false,
+            "Print a line per file laid out by this template, such as '{hash}\\t{size}\\t{path}'. Placeholders: id, hash, size, path, root, mtime, group, count, device, inode.",
+        ))
+        .arg(Arg::boolean(
+            "print0",
+            Some('0'),
+            "Print paths only, each ended by a NUL byte and each group by another, for xargs -0.",
+        ))
+        .arg(Arg::string(
+            "exec",
+            None,
+            false,
+            "Run this command for every copy but the kept one once the scan is over, e.g. 'echo {}'. {kept} and {group} stand for the kept copy and the group id.",
+        ))
+        .arg(Arg::string(
+            "exec-group",
+            None,
+            false,
+            "Run this command for every group once the scan is over, {} standing for all of its files.",
+        ))
+        .arg(Arg::boolean(
+            "delete",
+            None,
+            "Delete the copies not kept in every group once the scan is over.",
+        ))
+        .arg(Arg::boolean(
+            "trash",
+            None,
+            "With --delete, send the copies to the trash instead of deleting them for good.",
+        ))
+        .arg(Arg::string(
+            "link",
+            None,
+            false,
+            "Replace the copies not kept in every group with links to a kept one once the scan is over: hard, soft, or reflink.",
+        ))
+        .arg(Arg::string(
+            "move-to",
+            None,
+            false,
+            "Move the copies not kept in every group to this directory once the scan is over, at their path from the scan root.",
+        ))
+        .arg(Arg::string(
+            "link-style",
+            None,
+            false,
+            "With --link soft, how symlinks point to the kept copy: absolute (default) or relative.",
+        ))
+        .arg(Arg::string(
+            "min-savings",
+            None,
+            false,
+            "With --delete, --link or --move-to, only act on groups freeing at least this much, e.g. 10MB.",
+        ))
+        .arg(Arg::boolean(
+            "merge-metadata",
+            None,
+            "With --delete, --link or --move-to, give the kept copy the latest modification time, earliest creation time and every extended attribute of the others.",
+        ))
+        .arg(Arg::boolean(
+            "paranoid",
+            None,
+            "With --delete, --link or --move-to, compare every copy with the kept one byte by byte right before acting on it.",
+        ))
+        .arg(Arg::boolean(
+            "dry-run",
+            None,
+            "With --delete, --link or --move-to, only list what would be done and the space it would free.",
+        ))
+        .arg(Arg::string(
+            "script",
+            None,
+            false,
+            "With --delete and --keep, write a script deleting the copies instead of the report, to run later: sh or powershell.",
+        ))
+        .arg(Arg::string(
+            "journal",
+            None,
+            false,
+            "With --delete, --link or --move-to, file recording what was done, for `fdup undo`. Defaults to the cache directory.",
+        ))
+        .arg(Arg::boolean(
+            "no-journal",
+            None,
+            "With --delete, --link or --move-to, keep no record of what was done.",
+        ))
+        .arg(Arg::boolean(
+            "interactive",
+            Some('i'),
+            "With --delete, --link or --move-to, ask which copies to keep in every group and confirm before acting on the others.",
+        ))
+        .arg(Arg::boolean(
+            "edit",
+            None,
+            "With --delete, --link or --move-to, choose the copies to keep of every group at once in $VISUAL or $EDITOR. Starts from what --keep picks, the first copy otherwise.",
+        ))
+        .arg(Arg::string(
+            "keep",
+            None,
+            false,
+            "With --delete, --link or --move-to, the one copy kept in every group without asking: newest, oldest, first, shortest-path, or longest-path.",
+        ))
+        .arg(Arg::strings(
+            "prefer",
+            None,
+            false,
+            "With --keep, directory whose copies are kept first. Repeat it to prefer several, most preferred first.",
+        ))
+        .arg(Arg::string(
+            "keep-pattern",
+            None,
+            false,
+            "With --delete, --link or --move-to, never remove files whose whole path matches this pattern, e.g. '**/originals/**'.",
+        ))
+        .arg(Arg::string(
+            "keep-regex",
+            None,
+            false,
+            "With --delete, --link or --move-to, never remove files whose whole path matches this regular expression.",
+        ))
+        .arg(Arg::boolean(
+            "stream",
+            None,
+            "Print each group of duplicates as soon as it is confirmed.",
+        ))
+        .arg(Arg::boolean(
+            "verify",
+            None,
+            "Confirm duplicates with a byte by byte comparison after hashing.",
+        ))
+        .arg(Arg::boolean(
+            "retry-locked",
+            None,
+            "Try files locked by other processes again once every other file is hashed (Windows).",
+        ))
+        .arg(Arg::boolean(
+            "incremental",
+            None,
+            "Only hash files that are new or changed since the previous scan of this path.",
+        ))
+        .arg(Arg::string(
+            "checkpoint",
+            None,
+            false,
+            "Save the state of the scan to this file periodically, for `--resume`.",
+        ))
+        .arg(Arg::string(
+            "resume",
+            None,
+            false,
+            "Continue the scan saved in this checkpoint file.",
+        ))
+        .arg(Arg::string(
+            "threads",
+            Some('j'),
+            false,
+            "Number of threads walking and hashing, defaults to the number of logical CPUs.",
+        ))
+        .arg(Arg::string(
+            "max-open-files",
+            None,
+            false,
+            "Files kept open at once, defaults to half the limit of the process.",
+        ))
+        .arg(Arg::string(
+            "device-profile",
+            None,
+            false,
+            "Storage the files live on: auto, hdd or ssd. Spinning disks are read one file at a time.",
+        ))
+        .arg(Arg::string(
+            "memory-limit",
+            None,
+            false,
+            "Keep about this much of the file list in memory, e.g. 512M, spilling the rest to disk.",
+        ))
+        .arg(Arg::string(
+            "max-files",
+            None,
+            false,
+            "Stop looking for files once this many are found, and only compare those.",
+        ))
+        .arg(Arg::string(
+            "max-duration",
+            None,
+            false,
+            "Stop the scan after this long, e.g. 90s, 15m or 2h, and report what was found.",
+        ))
+        .arg(Arg::counter(
+            "verbose",
+            Some('v'),
+            "Also report skipped files and the time each step took on stderr. Given twice, also report every directory read.",
+        ))
+        .arg(Arg::boolean(
+            "debug",
+            None,
+            "Same as --verbose given twice: report every directory read on stderr as well.",
+        ))
+        .arg(Arg::string(
+            "log-file",
+            None,
+            false,
+            "Write every message, whatever the verbosity, to this file.",
+        ))
+        .arg(Arg::boolean(
+            "nice",
+            None,
+            "Run at low CPU and I/O priority, to keep the machine responsive.",
+        ))
+        .arg(Arg::boolean(
+            "stats",
+            None,
+            "Print file counts, bytes read, throughput and time spent per phase at the end.",
+        ))
+        .arg(Arg::boolean(
+            "progress",
+            None,
+            "Show counters and an estimated time left on stderr while scanning.",
+        ))
+        .parse()
 }
@@ -0,0 +1,40 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{ScanResult, config::Config, report::shown_path};
+
+/// Write a `<digest>  <path>` line for every file of every group to `location`, the
+/// format `sha256sum -c` and its siblings for the other algorithms check.
+/// Paths holding a backslash or a line break are escaped and their line starts with a
+/// backslash, as coreutils does.
+pub fn write_manifest(location: &Path, result: &ScanResult, config: &Config) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(location)?);
+    for group in &result.groups {
+        for file in &group.files {
+            let path = shown_path(&file.path, config)
+                .as_os_str()
+                .as_encoded_bytes();
+            let escaped = path.contains(&b'\\') || path.contains(&b'\n');
+            if escaped {
+                writer.write_all(b"\\")?;
+            }
+            write!(writer, "{}  ", group.hash.digest)?;
+            if escaped {
+                for &byte in path {
+                    match byte {
+                        b'\\' => writer.write_all(b"\\\\")?,
+                        b'\n' => writer.write_all(b"\\n")?,
+                        byte => writer.write_all(&[byte])?,
+                    }
+                }
+            } else {
+                writer.write_all(path)?;
+            }
+            writer.write_all(b"\n")?;
+        }
+    }
+    writer.flush()
+}
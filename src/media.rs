@@ -0,0 +1,70 @@
+use std::{
+    io,
+    sync::{
+        Mutex, PoisonError,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+};
+
+use crate::{devices, interrupt, log, progress::PROGRESS, walk::FileEntry};
+
+/// Key of every file among `files` that `key_of` gives one for, along with its index,
+/// computed on a pool of `threads` worker threads. Files that can not be read are
+/// left out. Used to compare images, audio, video and text by their contents rather
+/// than their bytes, where files of any size may match.
+pub fn key_each<T: Send>(
+    files: &[FileEntry],
+    threads: usize,
+    key_of: impl Fn(&FileEntry) -> io::Result<Option<T>> + Sync,
+) -> Vec<(usize, T)> {
+    let next = AtomicUsize::new(0);
+    let keys = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..threads.min(files.len()).max(1) {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(file) = files.get(index) else {
+                        break;
+                    };
+                    if interrupt::interrupted() {
+                        break;
+                    }
+                    let key = {
+                        let _permit = devices::acquire(file.file_id.map(|(device, _)| device));
+                        key_of(file)
+                    };
+                    PROGRESS.files_hashed.fetch_add(1, Ordering::Relaxed);
+                    PROGRESS.add_bytes(file.size);
+                    match key {
+                        Ok(Some(key)) => keys
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .push((index, key)),
+                        Ok(None) => {}
+                        // Files cut short by an interruption are not worth a message each.
+                        Err(_) if interrupt::interrupted() => {}
+                        Err(err) => log::warn!(
+                            "Error decoding file: `{}` {err}",
+                            file.path.to_string_lossy()
+                        ),
+                    }
+                }
+            });
+        }
+    });
+    let mut keys = keys.into_inner().unwrap_or_else(PoisonError::into_inner);
+    // Workers finish in any order, files keep the one they were found in.
+    keys.sort_unstable_by_key(|&(index, _)| index);
+    keys
+}
+
+/// Root of the set `item` belongs to, flattening the path to it on the way.
+pub fn find_set(parents: &mut [usize], mut item: usize) -> usize {
+    while parents[item] != item {
+        parents[item] = parents[parents[item]];
+        item = parents[item];
+    }
+    item
+}
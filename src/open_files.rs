@@ -0,0 +1,74 @@
+use std::sync::{Condvar, Mutex, PoisonError};
+
+/// Bound on the files held open at once by every thread together.
+struct OpenFiles {
+    limit: Mutex<Option<Limit>>,
+    released: Condvar,
+}
+
+struct Limit {
+    max: usize,
+    open: usize,
+}
+
+static OPEN_FILES: OpenFiles = OpenFiles {
+    limit: Mutex::new(None),
+    released: Condvar::new(),
+};
+
+/// Allow at most `max` files to be open at once from now on.
+pub fn set_limit(max: usize) {
+    let mut limit = OPEN_FILES
+        .limit
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    *limit = Some(Limit {
+        max: max.max(1),
+        open: 0,
+    });
+}
+
+/// Permission to keep files open, handed back when dropped.
+pub struct Permit {
+    count: usize,
+}
+
+/// Wait until `count` more files may be open. Files needed together are acquired in a
+/// single call, so two threads can never deadlock each holding half of what they need.
+pub fn acquire(count: usize) -> Permit {
+    let mut limit = OPEN_FILES
+        .limit
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let Some(current) = limit.as_ref() else {
+        return Permit { count: 0 };
+    };
+    let count = count.min(current.max);
+    while let Some(current) = limit.as_mut() {
+        if current.open + count <= current.max {
+            current.open += count;
+            break;
+        }
+        limit = OPEN_FILES
+            .released
+            .wait(limit)
+            .unwrap_or_else(PoisonError::into_inner);
+    }
+    Permit { count }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if self.count == 0 {
+            return;
+        }
+        let mut limit = OPEN_FILES
+            .limit
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(current) = limit.as_mut() {
+            current.open -= self.count;
+        }
+        OPEN_FILES.released.notify_all();
+    }
+}
@@ -0,0 +1,700 @@
+use std::{
+    ffi::{OsStr, OsString},
+    fs::{DirEntry, File, FileTimes, Metadata},
+    io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use std::{
+    ffi::{CStr, CString},
+    ptr,
+};
+
+#[cfg(target_os = "linux")]
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock, PoisonError},
+};
+
+#[cfg(target_os = "windows")]
+const FILE_ATTRIBUTE_HIDDEN: u32 = 0x00000002;
+
+/// Whether a file is hidden: the hidden attribute on Windows, a leading dot elsewhere.
+#[cfg(target_os = "windows")]
+pub fn is_hidden(_path: &Path, meta: &Metadata) -> bool {
+    use std::os::windows::fs::MetadataExt;
+
+    meta.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0
+}
+
+/// Whether a file is hidden: a leading dot, or the hidden flag Finder honors on macOS.
+#[cfg(target_os = "macos")]
+pub fn is_hidden(path: &Path, meta: &Metadata) -> bool {
+    use std::os::macos::fs::MetadataExt;
+
+    const UF_HIDDEN: u32 = 0x0000_8000;
+
+    meta.st_flags() & UF_HIDDEN != 0
+        || path
+            .file_name()
+            .is_some_and(|name| name.as_encoded_bytes().starts_with(b"."))
+}
+
+/// Whether a file is hidden: the hidden attribute on Windows, a leading dot elsewhere.
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+pub fn is_hidden(path: &Path, _meta: &Metadata) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.as_encoded_bytes().starts_with(b"."))
+}
+
+/// Whether the directory entry is a link to something else rather than the thing itself:
+/// a symlink, or on Windows any directory reparse point such as a junction or a mounted
+/// volume. Files stored as reparse points, like cloud placeholders, are not links.
+#[cfg(target_os = "windows")]
+pub fn is_link(entry: &DirEntry) -> bool {
+    use std::os::windows::fs::MetadataExt;
+
+    const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x0000_0010;
+    const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x0000_0400;
+
+    if entry.file_type().is_ok_and(|kind| kind.is_symlink()) {
+        return true;
+    }
+    // The metadata of a directory entry describes the entry itself, never its target.
+    entry.metadata().is_ok_and(|meta| {
+        let attributes = meta.file_attributes();
+        attributes & FILE_ATTRIBUTE_REPARSE_POINT != 0 && attributes & FILE_ATTRIBUTE_DIRECTORY != 0
+    })
+}
+
+/// Whether the directory entry is a symlink to something else rather than the thing itself.
+#[cfg(not(target_os = "windows"))]
+pub fn is_link(entry: &DirEntry) -> bool {
+    entry.file_type().is_ok_and(|kind| kind.is_symlink())
+}
+
+/// Whether `err` comes from a file opened exclusively, or locked, by another process.
+/// Only Windows refuses to open such files.
+#[cfg(target_os = "windows")]
+pub fn is_sharing_violation(err: &io::Error) -> bool {
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+    const ERROR_LOCK_VIOLATION: i32 = 33;
+
+    matches!(
+        err.raw_os_error(),
+        Some(ERROR_SHARING_VIOLATION | ERROR_LOCK_VIOLATION)
+    )
+}
+
+/// Whether `err` comes from a file opened exclusively, or locked, by another process.
+/// Only Windows refuses to open such files.
+#[cfg(not(target_os = "windows"))]
+pub fn is_sharing_violation(_err: &io::Error) -> bool {
+    false
+}
+
+/// Extensions of the directories macOS shows as a single file, such as applications
+/// and photo libraries.
+const BUNDLE_EXTENSIONS: &[&str] = &["app", "bundle", "framework", "photoslibrary", "plugin"];
+
+/// Whether the directory at `path` is a macOS bundle, told from its extension so that
+/// bundles copied elsewhere are recognized too.
+pub fn is_bundle(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| {
+        BUNDLE_EXTENSIONS
+            .iter()
+            .any(|bundle| extension.eq_ignore_ascii_case(bundle))
+    })
+}
+
+/// Device holding the file at `path`, described by `meta`, which changes past every
+/// mount point. Not available on all platforms.
+pub fn device_id(path: &Path, meta: &Metadata) -> Option<u64> {
+    file_id(meta)
+        .or_else(|| find_file_id(path))
+        .map(|(device, _)| device)
+}
+
+/// Device holding the open `file`, described by `meta`. Not available on all platforms.
+pub fn open_device_id(file: &File, meta: &Metadata) -> Option<u64> {
+    open_file_id(file, meta).map(|(device, _)| device)
+}
+
+/// Owning user and group ids of the file. Only available on Unix.
+#[cfg(unix)]
+pub fn owner(meta: &Metadata) -> Option<(u32, u32)> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some((meta.uid(), meta.gid()))
+}
+
+/// Owning user and group ids of the file. Only available on Unix.
+#[cfg(not(unix))]
+pub fn owner(_meta: &Metadata) -> Option<(u32, u32)> {
+    None
+}
+
+/// Id of the user called `name`, which may also be given as a number.
+#[cfg(unix)]
+pub fn user_id(name: &str) -> Option<u32> {
+    if let Ok(id) = name.parse() {
+        return Some(id);
+    }
+    let name = std::ffi::CString::new(name).ok()?;
+    // SAFETY: `name` is a valid C string. The entry returned lives in static storage and
+    // is read right away, before anything else could look another one up.
+    let entry = unsafe { libc::getpwnam(name.as_ptr()) };
+    // SAFETY: checked for null, otherwise a valid entry as above.
+    (!entry.is_null()).then(|| unsafe { (*entry).pw_uid })
+}
+
+/// Id of the group called `name`, which may also be given as a number.
+#[cfg(unix)]
+pub fn group_id(name: &str) -> Option<u32> {
+    if let Ok(id) = name.parse() {
+        return Some(id);
+    }
+    let name = std::ffi::CString::new(name).ok()?;
+    // SAFETY: as in `user_id`.
+    let entry = unsafe { libc::getgrnam(name.as_ptr()) };
+    // SAFETY: as in `user_id`.
+    (!entry.is_null()).then(|| unsafe { (*entry).gr_gid })
+}
+
+/// Identity of the file on disk as a `(device, inode)` pair, from its metadata `meta`.
+/// Every hardlink to the same data shares it. Only Unix metadata holds it, see
+/// `open_file_id` and `find_file_id` elsewhere.
+#[cfg(unix)]
+pub fn file_id(meta: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some((meta.dev(), meta.ino()))
+}
+
+/// Identity of the file on disk as a `(device, inode)` pair, from its metadata `meta`.
+/// Every hardlink to the same data shares it. Only Unix metadata holds it, see
+/// `open_file_id` and `find_file_id` elsewhere.
+#[cfg(not(unix))]
+pub fn file_id(_meta: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Identity of the open `file` as a `(volume serial number, file index)` pair, which
+/// plays the part of `(device, inode)`. Asked of the handle, metadata on Windows does
+/// not hold it.
+#[cfg(target_os = "windows")]
+pub fn open_file_id(file: &File, _meta: &Metadata) -> Option<(u64, u64)> {
+    handle_file_id(file)
+}
+
+/// Identity of the open `file` as a `(device, inode)` pair, from its metadata `meta`.
+#[cfg(not(target_os = "windows"))]
+pub fn open_file_id(_file: &File, meta: &Metadata) -> Option<(u64, u64)> {
+    file_id(meta)
+}
+
+/// `(volume serial number, file index)` of the open `file`, from its handle.
+#[cfg(target_os = "windows")]
+fn handle_file_id(file: &File) -> Option<(u64, u64)> {
+    use std::{ffi::c_void, os::windows::io::AsRawHandle};
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct FileTime {
+        low: u32,
+        high: u32,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct ByHandleFileInformation {
+        file_attributes: u32,
+        creation_time: FileTime,
+        last_access_time: FileTime,
+        last_write_time: FileTime,
+        volume_serial_number: u32,
+        file_size_high: u32,
+        file_size_low: u32,
+        number_of_links: u32,
+        file_index_high: u32,
+        file_index_low: u32,
+    }
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetFileInformationByHandle(
+            file: *mut c_void,
+            information: *mut ByHandleFileInformation,
+        ) -> i32;
+    }
+
+    let mut information = ByHandleFileInformation::default();
+    // SAFETY: the handle stays open as long as `file` is borrowed, and `information` is a
+    // valid place for the call to write to.
+    if unsafe { GetFileInformationByHandle(file.as_raw_handle(), &mut information) } == 0 {
+        return None;
+    }
+    Some((
+        u64::from(information.volume_serial_number),
+        (u64::from(information.file_index_high) << 32) | u64::from(information.file_index_low),
+    ))
+}
+
+/// Identity of the file at `path` when its metadata does not hold it. The file is opened
+/// on Windows to ask for it, so this is only worth it when the identity is needed.
+#[cfg(target_os = "windows")]
+pub fn find_file_id(path: &Path) -> Option<(u64, u64)> {
+    use std::os::windows::fs::OpenOptionsExt;
+
+    // Lets directories be opened too.
+    const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
+
+    // No access is asked for, so even files other processes keep locked can be opened.
+    let file = std::fs::OpenOptions::new()
+        .access_mode(0)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+        .open(path)
+        .ok()?;
+    handle_file_id(&file)
+}
+
+/// Identity of the file at `path` when its metadata does not hold it. Not available on
+/// this platform beyond what `file_id` tells.
+#[cfg(not(target_os = "windows"))]
+pub fn find_file_id(_path: &Path) -> Option<(u64, u64)> {
+    None
+}
+
+/// Path made of raw `bytes` read from another program, taken as they are.
+#[cfg(unix)]
+pub fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+/// Path made of raw `bytes` read from another program, expected to be UTF-8.
+#[cfg(not(unix))]
+pub fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Whether `device` is a spinning disk, from the `rotational` flag Linux exposes for
+/// every block device. Answers are remembered per device.
+#[cfg(target_os = "linux")]
+pub fn is_rotational(device: u64) -> bool {
+    static DEVICES: OnceLock<Mutex<HashMap<u64, bool>>> = OnceLock::new();
+    let mut devices = DEVICES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    *devices.entry(device).or_insert_with(|| {
+        let major = ((device >> 8) & 0xfff) | ((device >> 32) & !0xfff);
+        let minor = (device & 0xff) | ((device >> 12) & !0xff);
+        let block = std::path::PathBuf::from(format!("/sys/dev/block/{major}:{minor}"));
+        // Partitions have no queue of their own, the flag lives on the parent disk.
+        [block.join("queue"), block.join("..").join("queue")]
+            .iter()
+            .find_map(|queue| std::fs::read_to_string(queue.join("rotational")).ok())
+            .is_some_and(|flag| flag.trim() == "1")
+    })
+}
+
+/// Whether `device` is a spinning disk. Only detected on Linux.
+#[cfg(not(target_os = "linux"))]
+pub fn is_rotational(_device: u64) -> bool {
+    false
+}
+
+/// Nice value applied to the process when running in the background.
+#[cfg(all(unix, not(target_os = "macos")))]
+const BACKGROUND_NICE: i32 = 10;
+
+/// Lower the CPU and I/O priority of the process, so a scan does not get in the way of
+/// interactive work. Must be called before starting any thread: Linux applies both to
+/// the calling thread only, and the threads it creates afterwards inherit them.
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn lower_priority() -> io::Result<()> {
+    // SAFETY: plain system calls on the current thread, no memory is handed over.
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, BACKGROUND_NICE) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // Lowest best effort I/O priority, as `ionice -c2 -n7` does. The idle class could
+    // starve the scan for good on a busy disk.
+    #[cfg(target_os = "linux")]
+    {
+        const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+        const IOPRIO_CLASS_BEST_EFFORT: libc::c_int = 2;
+        const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+        const IOPRIO_LOWEST_LEVEL: libc::c_int = 7;
+        let priority = IOPRIO_CLASS_BEST_EFFORT << IOPRIO_CLASS_SHIFT | IOPRIO_LOWEST_LEVEL;
+        // SAFETY: as above.
+        if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, priority) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Lower the CPU and I/O priority of the process, so a scan does not get in the way of
+/// interactive work. Background priority throttles both for the whole process.
+#[cfg(target_os = "macos")]
+pub fn lower_priority() -> io::Result<()> {
+    // SAFETY: plain system call on the current process, no memory is handed over.
+    if unsafe { libc::setpriority(libc::PRIO_DARWIN_PROCESS, 0, libc::PRIO_DARWIN_BG) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Lower the CPU and I/O priority of the process, so a scan does not get in the way of
+/// interactive work. Background mode lowers CPU, I/O and memory priorities at once.
+#[cfg(target_os = "windows")]
+pub fn lower_priority() -> io::Result<()> {
+    use std::ffi::c_void;
+
+    const PROCESS_MODE_BACKGROUND_BEGIN: u32 = 0x0010_0000;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetCurrentProcess() -> *mut c_void;
+        fn SetPriorityClass(process: *mut c_void, priority_class: u32) -> i32;
+    }
+
+    // SAFETY: the pseudo handle of the current process is always valid.
+    if unsafe { SetPriorityClass(GetCurrentProcess(), PROCESS_MODE_BACKGROUND_BEGIN) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Lower the CPU and I/O priority of the process. Not supported on this platform.
+#[cfg(not(any(unix, target_os = "windows")))]
+pub fn lower_priority() -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// Maximum number of files the process may have open, from its soft `RLIMIT_NOFILE`.
+#[cfg(unix)]
+pub fn open_file_limit() -> Option<u64> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: `limit` is a valid place for the system call to write to.
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+        return None;
+    }
+    // `rlim_t` is not 64 bits wide everywhere.
+    #[allow(clippy::unnecessary_cast)]
+    (limit.rlim_cur != libc::RLIM_INFINITY).then_some(limit.rlim_cur as u64)
+}
+
+/// Maximum number of files the process may have open. Handles are only limited by
+/// memory on Windows.
+#[cfg(not(unix))]
+pub fn open_file_limit() -> Option<u64> {
+    None
+}
+
+/// Bytes the kernel is asked to start reading ahead as soon as a file is opened.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+const READAHEAD_SIZE: libc::off_t = 16 * 1024 * 1024;
+
+/// Open a file that is about to be read from start to end, hinting the system to read
+/// ahead aggressively: `posix_fadvise` where available, `FILE_FLAG_SEQUENTIAL_SCAN`
+/// on Windows.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+pub fn open_sequential(path: &Path) -> io::Result<File> {
+    use std::os::fd::AsRawFd;
+
+    let file = File::open(path)?;
+    let fd = file.as_raw_fd();
+    // SAFETY: `fd` stays open for both calls. Hints are only advice, failures are ignored.
+    unsafe {
+        libc::posix_fadvise(fd, 0, 0, libc::POSIX_FADV_SEQUENTIAL);
+        libc::posix_fadvise(fd, 0, READAHEAD_SIZE, libc::POSIX_FADV_WILLNEED);
+    }
+    Ok(file)
+}
+
+/// Open a file that is about to be read from start to end, hinting the system to read
+/// ahead aggressively: `posix_fadvise` where available, `FILE_FLAG_SEQUENTIAL_SCAN`
+/// on Windows.
+#[cfg(target_os = "windows")]
+pub fn open_sequential(path: &Path) -> io::Result<File> {
+    use std::os::windows::fs::OpenOptionsExt;
+
+    const FILE_FLAG_SEQUENTIAL_SCAN: u32 = 0x0800_0000;
+
+    std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(FILE_FLAG_SEQUENTIAL_SCAN)
+        .open(path)
+}
+
+/// Open a file that is about to be read from start to end. No hint is available here.
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "windows"
+)))]
+pub fn open_sequential(path: &Path) -> io::Result<File> {
+    File::open(path)
+}
+
+/// Create a symlink at `link` pointing to the file at `target`.
+#[cfg(unix)]
+pub fn symlink_file(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+/// Create a symlink at `link` pointing to the file at `target`. Needs developer mode or
+/// the matching privilege on Windows.
+#[cfg(target_os = "windows")]
+pub fn symlink_file(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+/// Create a symlink at `link` pointing to the file at `target`. Not supported on this
+/// platform.
+#[cfg(not(any(unix, target_os = "windows")))]
+pub fn symlink_file(_target: &Path, _link: &Path) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// Create a file at `link` sharing the on-disk extents of the file at `source`, through
+/// `FICLONE` on btrfs, XFS and other copy-on-write file systems.
+#[cfg(target_os = "linux")]
+pub fn reflink(source: &Path, link: &Path) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    const FICLONE: libc::c_ulong = 0x4004_9409;
+
+    let original = File::open(source)?;
+    let clone = File::options().write(true).create_new(true).open(link)?;
+    // SAFETY: both descriptors stay open for the call, no memory is handed over.
+    if unsafe { libc::ioctl(clone.as_raw_fd(), FICLONE as _, original.as_raw_fd()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    clone.set_permissions(original.metadata()?.permissions())
+}
+
+/// Create a file at `link` sharing the on-disk extents of the file at `source`, through
+/// `clonefile` on APFS.
+#[cfg(target_os = "macos")]
+pub fn reflink(source: &Path, link: &Path) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    unsafe extern "C" {
+        fn clonefile(
+            source: *const libc::c_char,
+            destination: *const libc::c_char,
+            flags: u32,
+        ) -> libc::c_int;
+    }
+
+    let source = CString::new(source.as_os_str().as_bytes())?;
+    let link = CString::new(link.as_os_str().as_bytes())?;
+    // SAFETY: both paths are NUL terminated and outlive the call.
+    if unsafe { clonefile(source.as_ptr(), link.as_ptr(), 0) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Create a file at `link` sharing the on-disk extents of the file at `source`, through
+/// `FSCTL_DUPLICATE_EXTENTS_TO_FILE` on ReFS.
+#[cfg(target_os = "windows")]
+pub fn reflink(source: &Path, link: &Path) -> io::Result<()> {
+    use std::{ffi::c_void, os::windows::io::AsRawHandle, ptr};
+
+    const FSCTL_DUPLICATE_EXTENTS_TO_FILE: u32 = 0x0009_8344;
+    // Extents are duplicated whole clusters at a time, and ReFS clusters are 4 or 64 KiB.
+    const CLUSTER_ALIGNMENT: u64 = 64 * 1024;
+
+    #[repr(C)]
+    struct DuplicateExtentsData {
+        file_handle: *mut c_void,
+        source_file_offset: i64,
+        target_file_offset: i64,
+        byte_count: i64,
+    }
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn DeviceIoControl(
+            device: *mut c_void,
+            control_code: u32,
+            in_buffer: *const c_void,
+            in_buffer_size: u32,
+            out_buffer: *mut c_void,
+            out_buffer_size: u32,
+            bytes_returned: *mut u32,
+            overlapped: *mut c_void,
+        ) -> i32;
+    }
+
+    let original = File::open(source)?;
+    let size = original.metadata()?.len();
+    let clone = File::options().write(true).create_new(true).open(link)?;
+    clone.set_len(size)?;
+    let data = DuplicateExtentsData {
+        file_handle: original.as_raw_handle(),
+        source_file_offset: 0,
+        target_file_offset: 0,
+        byte_count: (size.div_ceil(CLUSTER_ALIGNMENT) * CLUSTER_ALIGNMENT) as i64,
+    };
+    let mut returned = 0;
+    // SAFETY: both handles stay open for the call, and `data` outlives it.
+    let result = unsafe {
+        DeviceIoControl(
+            clone.as_raw_handle(),
+            FSCTL_DUPLICATE_EXTENTS_TO_FILE,
+            (&raw const data).cast(),
+            size_of::<DuplicateExtentsData>() as u32,
+            ptr::null_mut(),
+            0,
+            &mut returned,
+            ptr::null_mut(),
+        )
+    };
+    if result == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Create a file at `link` sharing the on-disk extents of the file at `source`. Not
+/// supported on this platform.
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+pub fn reflink(_source: &Path, _link: &Path) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// Extended attributes of the file at `path`, as `(name, value)` pairs. Attributes that
+/// vanish while being read are left out.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn xattrs(path: &Path) -> io::Result<Vec<(OsString, Vec<u8>)>> {
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: `path` is NUL terminated, a null buffer only asks for the size needed.
+    let size = unsafe { list_xattrs(&path, ptr::null_mut(), 0) };
+    if size < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut names = vec![0u8; size as usize];
+    // SAFETY: `names` holds as many bytes as the call is told it does.
+    let size = unsafe { list_xattrs(&path, names.as_mut_ptr().cast(), names.len()) };
+    if size < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    names.truncate(size as usize);
+
+    let mut attributes = Vec::new();
+    for name in names
+        .split(|&byte| byte == 0)
+        .filter(|name| !name.is_empty())
+    {
+        let name = CString::new(name)?;
+        // SAFETY: as above.
+        let size = unsafe { get_xattr(&path, &name, ptr::null_mut(), 0) };
+        if size < 0 {
+            continue;
+        }
+        let mut value = vec![0u8; size as usize];
+        // SAFETY: as above.
+        let size = unsafe { get_xattr(&path, &name, value.as_mut_ptr().cast(), value.len()) };
+        if size < 0 {
+            continue;
+        }
+        value.truncate(size as usize);
+        attributes.push((OsString::from_vec(name.into_bytes()), value));
+    }
+    Ok(attributes)
+}
+
+/// Extended attributes of the file at `path`. Not supported on this platform.
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn xattrs(_path: &Path) -> io::Result<Vec<(OsString, Vec<u8>)>> {
+    Ok(Vec::new())
+}
+
+/// Set the extended attribute `name` of the file at `path` to `value`.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn set_xattr(path: &Path, name: &OsStr, value: &[u8]) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let name = CString::new(name.as_bytes())?;
+    // SAFETY: both strings are NUL terminated and `value` holds as many bytes as told.
+    if unsafe { put_xattr(&path, &name, value.as_ptr().cast(), value.len()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Set an extended attribute of the file at `path`. Not supported on this platform.
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn set_xattr(_path: &Path, _name: &OsStr, _value: &[u8]) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn list_xattrs(path: &CStr, names: *mut libc::c_char, size: usize) -> isize {
+    unsafe { libc::listxattr(path.as_ptr(), names, size) }
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn get_xattr(path: &CStr, name: &CStr, value: *mut libc::c_void, size: usize) -> isize {
+    unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), value, size) }
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn put_xattr(path: &CStr, name: &CStr, value: *const libc::c_void, size: usize) -> i32 {
+    unsafe { libc::setxattr(path.as_ptr(), name.as_ptr(), value, size, 0) }
+}
+
+// macOS takes a position, only meaningful for resource forks, and options on top.
+#[cfg(target_os = "macos")]
+unsafe fn list_xattrs(path: &CStr, names: *mut libc::c_char, size: usize) -> isize {
+    unsafe { libc::listxattr(path.as_ptr(), names, size, 0) }
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn get_xattr(path: &CStr, name: &CStr, value: *mut libc::c_void, size: usize) -> isize {
+    unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), value, size, 0, 0) }
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn put_xattr(path: &CStr, name: &CStr, value: *const libc::c_void, size: usize) -> i32 {
+    unsafe { libc::setxattr(path.as_ptr(), name.as_ptr(), value, size, 0, 0) }
+}
+
+/// `times` along with the creation time `created`, where it can be set.
+#[cfg(target_os = "macos")]
+pub fn with_created(times: FileTimes, created: SystemTime) -> FileTimes {
+    use std::os::macos::fs::FileTimesExt;
+
+    times.set_created(created)
+}
+
+/// `times` along with the creation time `created`, where it can be set.
+#[cfg(target_os = "windows")]
+pub fn with_created(times: FileTimes, created: SystemTime) -> FileTimes {
+    use std::os::windows::fs::FileTimesExt;
+
+    times.set_created(created)
+}
+
+/// `times` along with the creation time `created`, where it can be set. It can not be
+/// on this platform, `times` is left as it is.
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+pub fn with_created(times: FileTimes, _created: SystemTime) -> FileTimes {
+    times
+}
@@ -0,0 +1,166 @@
+use std::{
+    io::Write,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU8, AtomicU64, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::units::{format_duration, format_size};
+
+/// How often the progress line is redrawn.
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Step of the scan currently running.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Phase {
+    Walking,
+    Prefix,
+    Tail,
+    Hashing,
+    Verifying,
+}
+
+impl Phase {
+    fn from_u8(value: u8) -> Phase {
+        match value {
+            1 => Phase::Prefix,
+            2 => Phase::Tail,
+            3 => Phase::Hashing,
+            4 => Phase::Verifying,
+            _ => Phase::Walking,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Phase::Walking => "walking",
+            Phase::Prefix => "prefix",
+            Phase::Tail => "tail",
+            Phase::Hashing => "hashing",
+            Phase::Verifying => "verifying",
+        }
+    }
+}
+
+/// Counters updated by walkers and hashers while a scan runs.
+pub struct Progress {
+    phase: AtomicU8,
+    pub directories: AtomicU64,
+    pub files_found: AtomicU64,
+    pub files_hashed: AtomicU64,
+    pub bytes_hashed: AtomicU64,
+    /// Files that could not be read because another process holds them locked.
+    pub files_locked: AtomicU64,
+    /// Bytes expected to be read during the current phase, and how many of them were.
+    phase_total_bytes: AtomicU64,
+    phase_bytes: AtomicU64,
+}
+
+/// Progress of the scan in flight, shared by every thread.
+pub static PROGRESS: Progress = Progress {
+    phase: AtomicU8::new(Phase::Walking as u8),
+    directories: AtomicU64::new(0),
+    files_found: AtomicU64::new(0),
+    files_hashed: AtomicU64::new(0),
+    bytes_hashed: AtomicU64::new(0),
+    files_locked: AtomicU64::new(0),
+    phase_total_bytes: AtomicU64::new(0),
+    phase_bytes: AtomicU64::new(0),
+};
+
+impl Progress {
+    /// Move on to `phase`, expected to read `total_bytes` in total.
+    pub fn start_phase(&self, phase: Phase, total_bytes: u64) {
+        self.phase_bytes.store(0, Ordering::Relaxed);
+        self.phase_total_bytes.store(total_bytes, Ordering::Relaxed);
+        self.phase.store(phase as u8, Ordering::Relaxed);
+    }
+
+    /// Account for `bytes` read while hashing.
+    pub fn add_bytes(&self, bytes: u64) {
+        self.bytes_hashed.fetch_add(bytes, Ordering::Relaxed);
+        self.phase_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Account for `bytes` the current phase expected to read but did not need to,
+    /// for instance because the hash was cached.
+    pub fn skip_bytes(&self, bytes: u64) {
+        self.phase_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+}
+
+/// Progress line redrawn on stderr by a background thread.
+pub struct ProgressDisplay {
+    done: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl ProgressDisplay {
+    /// Start drawing the progress line until `finish` is called.
+    pub fn start() -> ProgressDisplay {
+        let done = Arc::new(AtomicBool::new(false));
+        let thread = thread::spawn({
+            let done = done.clone();
+            move || {
+                let mut line_length = 0;
+                let mut phase = Phase::Walking;
+                let mut phase_start = Instant::now();
+                while !done.load(Ordering::Relaxed) {
+                    let current = Phase::from_u8(PROGRESS.phase.load(Ordering::Relaxed));
+                    if current != phase {
+                        phase = current;
+                        phase_start = Instant::now();
+                    }
+                    let line = render(phase, phase_start.elapsed());
+                    eprint!("\r{line:<line_length$}");
+                    let _ = std::io::stderr().flush();
+                    line_length = line.len();
+                    thread::sleep(REFRESH_INTERVAL);
+                }
+                // Wipe the line so the report starts on a clean one.
+                eprint!("\r{:line_length$}\r", "");
+            }
+        });
+        ProgressDisplay { done, thread }
+    }
+
+    /// Stop drawing and clear the progress line.
+    pub fn finish(self) {
+        self.done.store(true, Ordering::Relaxed);
+        let _ = self.thread.join();
+    }
+}
+
+/// Text of the progress line, e.g.
+/// `[hashing] 120 dirs | 5400 files | 300 hashed | 1.2 GiB / 3.0 GiB | ETA 1m 10s`
+fn render(phase: Phase, elapsed: Duration) -> String {
+    let directories = PROGRESS.directories.load(Ordering::Relaxed);
+    let files_found = PROGRESS.files_found.load(Ordering::Relaxed);
+    let files_hashed = PROGRESS.files_hashed.load(Ordering::Relaxed);
+    let mut line = format!(
+        "[{}] {directories} dirs | {files_found} files | {files_hashed} hashed",
+        phase.label()
+    );
+    if phase == Phase::Walking {
+        return line;
+    }
+
+    let total = PROGRESS.phase_total_bytes.load(Ordering::Relaxed);
+    let processed = PROGRESS.phase_bytes.load(Ordering::Relaxed).min(total);
+    line.push_str(&format!(
+        " | {} / {}",
+        format_size(processed),
+        format_size(total)
+    ));
+    // Estimate from the average rate of the current phase.
+    if processed > 0 && elapsed.as_secs_f64() > 1.0 {
+        let rate = processed as f64 / elapsed.as_secs_f64();
+        let remaining = Duration::from_secs_f64((total - processed) as f64 / rate);
+        line.push_str(&format!(" | ETA {}", format_duration(remaining)));
+    }
+    line
+}
@@ -0,0 +1,904 @@
+use std::{
+    cmp::Reverse,
+    fs::File,
+    io::{self, BufWriter, IsTerminal, Write},
+    path::Path,
+    str::FromStr,
+    sync::{
+        Mutex, PoisonError,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use crate::{
+    DuplicateGroup, ScanResult, actions,
+    config::Config,
+    dirs::DirectoryGroup,
+    hash::to_hex,
+    interrupt::StopReason,
+    log,
+    units::{format_size, format_time},
+    walk::{FileEntry, relative_to_root, root_of},
+};
+
+/// Styles of the parts of the text output worth highlighting.
+const HEADER: &str = "\x1b[1;36m";
+const KEEP: &str = "\x1b[32m";
+const WASTED: &str = "\x1b[1;33m";
+const RESET: &str = "\x1b[0m";
+
+/// File the report goes to instead of stdout, with `--output`.
+static OUTPUT_FILE: Mutex<Option<BufWriter<File>>> = Mutex::new(None);
+
+/// Groups written with `--template` so far, numbering them from 1 whether they are
+/// streamed or not.
+static TEMPLATE_GROUPS: AtomicUsize = AtomicUsize::new(0);
+
+/// When the text output is colored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Only on a terminal, unless the `NO_COLOR` environment variable is set.
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether the output gets colored, `to_stdout` when it is not written to a file.
+    pub fn enabled(self, to_stdout: bool) -> bool {
+        match self {
+            ColorChoice::Auto => {
+                std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                    && to_stdout
+                    && io::stdout().is_terminal()
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!(
+                "Unknown color choice `{name}`, expected one of: auto, always, never"
+            )),
+        }
+    }
+}
+
+/// Order groups of duplicates are printed in, the most interesting first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GroupOrder {
+    /// Largest files first.
+    Size,
+    /// Most copies first.
+    Count,
+    /// Most space taken by extra copies first.
+    Wasted,
+    /// By the path of their first file, alphabetically.
+    Path,
+}
+
+impl FromStr for GroupOrder {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "size" => Ok(GroupOrder::Size),
+            "count" => Ok(GroupOrder::Count),
+            "wasted" => Ok(GroupOrder::Wasted),
+            "path" => Ok(GroupOrder::Path),
+            _ => Err(format!(
+                "Unknown sort order `{name}`, expected one of: size, count, wasted, path"
+            )),
+        }
+    }
+}
+
+/// Sort `groups` by `order`, or the other way around when `reverse` is set. Ties are
+/// broken by the path of the first file, so the order never depends on hashing.
+pub fn sort_groups(groups: &mut [DuplicateGroup], order: GroupOrder, reverse: bool) {
+    groups.sort_by(|a, b| {
+        let key = |group: &DuplicateGroup| match order {
+            GroupOrder::Size => group.size_range().1,
+            GroupOrder::Count => group.files.len() as u64,
+            GroupOrder::Wasted => group.wasted_bytes(),
+            // Paths alone decide.
+            GroupOrder::Path => 0,
+        };
+        let ordering =
+            (Reverse(key(a)), &a.files[0].path).cmp(&(Reverse(key(b)), &b.files[0].path));
+        match reverse {
+            true => ordering.reverse(),
+            false => ordering,
+        }
+    });
+}
+
+/// Keep the `top` groups with the most reclaimable space, returning how many others were
+/// dropped.
+pub fn keep_top(groups: &mut Vec<DuplicateGroup>, top: usize) -> usize {
+    sort_groups(groups, GroupOrder::Wasted, false);
+    let omitted = groups.len().saturating_sub(top);
+    groups.truncate(top);
+    omitted
+}
+
+/// How results are written to stdout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Framed groups, meant to be read in a terminal.
+    Text,
+    /// A single JSON document, written once the scan is over.
+    Json,
+    /// One JSON object per group of duplicates, each on its own line as soon as it is
+    /// confirmed.
+    JsonLines,
+    /// One row per file, for spreadsheets, written once the scan is over.
+    Csv,
+    /// Paths one per line, groups separated by a blank line, as fdupes and jdupes print them.
+    Fdupes,
+    /// A summary and a table per group, for tickets and wikis.
+    Markdown,
+    /// Paths alone, each ended by a NUL byte and each group by another one, for `xargs -0`.
+    /// Chosen with `--print0` rather than by name.
+    Print0,
+    /// A line per file laid out by the user, chosen with `--template` rather than by name.
+    Template,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "jsonl" => Ok(OutputFormat::JsonLines),
+            "csv" => Ok(OutputFormat::Csv),
+            "fdupes" => Ok(OutputFormat::Fdupes),
+            "markdown" | "md" => Ok(OutputFormat::Markdown),
+            _ => Err(format!(
+                "Unknown output format `{name}`, expected one of: text, json, jsonl, csv, fdupes, markdown"
+            )),
+        }
+    }
+}
+
+/// What a placeholder of a `--template` stands for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    Id,
+    Hash,
+    Size,
+    Path,
+    /// The scan path the file was found under.
+    Root,
+    Modified,
+    Group,
+    Count,
+    Device,
+    Inode,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum TemplatePart {
+    Literal(String),
+    Field(Field),
+}
+
+/// Layout of the line printed for every file with `--template`, such as
+/// `{hash}\t{size}\t{path}`. Braces are written `{{` and `}}`, and `\t`, `\n` and
+/// `\\` stand for a tab, a line break and a backslash.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template(Vec<TemplatePart>);
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let Some((name, rest)) = chars.as_str().split_once('}') else {
+                        return Err(format!("Unclosed placeholder in template `{template}`"));
+                    };
+                    let field = match name {
+                        "id" => Field::Id,
+                        "hash" => Field::Hash,
+                        "size" => Field::Size,
+                        "path" => Field::Path,
+                        "root" => Field::Root,
+                        "mtime" => Field::Modified,
+                        "group" => Field::Group,
+                        "count" => Field::Count,
+                        "device" => Field::Device,
+                        "inode" => Field::Inode,
+                        _ => {
+                            return Err(format!(
+                                "Unknown placeholder `{{{name}}}`, expected one of: id, hash, size, path, root, mtime, group, count, device, inode"
+                            ));
+                        }
+                    };
+                    chars = rest.chars();
+                    if !literal.is_empty() {
+                        parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(TemplatePart::Field(field));
+                }
+                '}' => {
+                    return Err(format!(
+                        "Unmatched `}}` in template `{template}`, write `}}}}` for a brace"
+                    ));
+                }
+                '\\' => match chars.next() {
+                    Some('t') => literal.push('\t'),
+                    Some('n') => literal.push('\n'),
+                    Some('\\') => literal.push('\\'),
+                    Some(other) => {
+                        literal.push('\\');
+                        literal.push(other);
+                    }
+                    None => literal.push('\\'),
+                },
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(TemplatePart::Literal(literal));
+        }
+        Ok(Template(parts))
+    }
+}
+
+/// Write the report to a new file at `location` instead of stdout.
+pub fn set_output(location: &Path) -> io::Result<()> {
+    let file = BufWriter::new(File::create(location)?);
+    *OUTPUT_FILE.lock().unwrap_or_else(PoisonError::into_inner) = Some(file);
+    Ok(())
+}
+
+/// Run `write` against wherever the report goes, flushing it right after so streamed
+/// groups show up as they are found.
+pub fn emit(write: impl FnOnce(&mut dyn Write) -> io::Result<()>) {
+    let mut file = OUTPUT_FILE.lock().unwrap_or_else(PoisonError::into_inner);
+    let result = match file.as_mut() {
+        Some(file) => write(file).and_then(|()| file.flush()),
+        None => {
+            let mut stdout = io::stdout().lock();
+            write(&mut stdout).and_then(|()| stdout.flush())
+        }
+    };
+    // Nothing is left to do once whoever reads the report stops listening.
+    if let Err(err) = result
+        && err.kind() != io::ErrorKind::BrokenPipe
+    {
+        log::error!("Error writing results: {err}");
+    }
+}
+
+/// Print a group confirmed while streaming, for the formats written as the scan goes.
+pub fn print_streamed(group: &DuplicateGroup, config: &Config) {
+    emit(|out| match config.format {
+        OutputFormat::Text if !config.quiet => print_group(out, group, config),
+        OutputFormat::JsonLines => writeln!(out, "{}", json_group(group, config)),
+        OutputFormat::Print0 => print_nul_separated(out, group, config),
+        OutputFormat::Fdupes => print_fdupes_group(out, group, config),
+        OutputFormat::Template => print_template_group(out, group, config),
+        OutputFormat::Text | OutputFormat::Json | OutputFormat::Csv | OutputFormat::Markdown => {
+            Ok(())
+        }
+    });
+}
+
+/// Prints the results of the execution including all duplicates found if any.
+pub fn print_results(result: &ScanResult, config: &Config) {
+    emit(|out| match config.format {
+        OutputFormat::Text => print_text(out, result, config),
+        OutputFormat::Json => writeln!(out, "{}", json_document(result, config)),
+        // Every group was written as it was confirmed, a summary would not parse as one.
+        OutputFormat::JsonLines => Ok(()),
+        OutputFormat::Csv => print_csv(out, result, config),
+        OutputFormat::Print0 if !config.stream => result
+            .groups
+            .iter()
+            .try_for_each(|group| print_nul_separated(out, group, config)),
+        OutputFormat::Fdupes if !config.stream => result
+            .groups
+            .iter()
+            .try_for_each(|group| print_fdupes_group(out, group, config)),
+        OutputFormat::Template if !config.stream => result
+            .groups
+            .iter()
+            .try_for_each(|group| print_template_group(out, group, config)),
+        OutputFormat::Print0 | OutputFormat::Fdupes | OutputFormat::Template => Ok(()),
+        OutputFormat::Markdown => print_markdown(out, result, config),
+    });
+}
+
+/// Prints a single group of duplicates. The copy that is kept, as chosen by `--keep`
+/// and `--prefer` or else the first one, is highlighted along with the header when
+/// colors are enabled.
+fn print_group(out: &mut dyn Write, group: &DuplicateGroup, config: &Config) -> io::Result<()> {
+    let kept = actions::suggested_keep(group, config);
+    writeln!(
+        out,
+        "{}",
+        paint("------- Multiple Entries Found -------", HEADER, config)
+    )?;
+    for (index, file) in group.files.iter().enumerate() {
+        let line = format!(
+            "{:>5} -> `{}`  {}",
+            index + 1,
+            shown_path(&file.path, config).to_string_lossy(),
+            file_details(file)
+        );
+        match Some(index) == kept {
+            true => writeln!(out, "{}", paint(&line, KEEP, config))?,
+            false => writeln!(out, "{line}")?,
+        }
+    }
+    writeln!(
+        out,
+        "  Group {}: {}, {} reclaimable",
+        group_id(group, config),
+        copies(group),
+        paint(&format_size(group.wasted_bytes()), WASTED, config)
+    )?;
+    writeln!(out, "--------------------------------------")?;
+    Ok(())
+}
+
+/// How many copies `group` holds and their size.
+fn copies(group: &DuplicateGroup) -> String {
+    format!("{} copies of {}", group.files.len(), format_sizes(group))
+}
+
+/// Size of the copies of `group`, or the range of their sizes when they were compared
+/// by their contents and differ.
+pub fn format_sizes(group: &DuplicateGroup) -> String {
+    match group.size_range() {
+        (smallest, largest) if smallest != largest => {
+            format!("{} to {}", format_size(smallest), format_size(largest))
+        }
+        (_, size) => format_size(size),
+    }
+}
+
+/// Prints a group of identical directories, the first one highlighted like the copy
+/// kept of a group of files.
+fn print_directory_group(
+    out: &mut dyn Write,
+    group: &DirectoryGroup,
+    config: &Config,
+) -> io::Result<()> {
+    writeln!(
+        out,
+        "{}",
+        paint("------- Identical Directories --------", HEADER, config)
+    )?;
+    for (index, directory) in group.directories.iter().enumerate() {
+        let line = format!(
+            "{:>5} -> `{}`",
+            index + 1,
+            shown_path(directory, config).to_string_lossy()
+        );
+        match index {
+            0 => writeln!(out, "{}", paint(&line, KEEP, config))?,
+            _ => writeln!(out, "{line}")?,
+        }
+    }
+    writeln!(
+        out,
+        "  {} copies of {} files taking {}, {} reclaimable",
+        group.directories.len(),
+        group.files,
+        format_size(group.size),
+        paint(&format_size(group.wasted_bytes()), WASTED, config)
+    )?;
+    writeln!(out, "--------------------------------------")?;
+    Ok(())
+}
+
+/// Size, modification time and, where available, inode and device of `file`, to help
+/// choose which copy to keep.
+fn file_details(file: &FileEntry) -> String {
+    let mut details = format_size(file.size);
+    if let Some(modified) = file.modified {
+        details.push_str(&format!(", modified {}", format_time(modified)));
+    }
+    if let Some((device, inode)) = file.file_id {
+        details.push_str(&format!(", inode {inode} on device {device}"));
+    }
+    details
+}
+
+/// Prints the totals and every group of duplicates as text, leaving the groups out
+/// with `--quiet` and the totals with `--no-summary`.
+/// Streamed groups were printed while scanning already, only the totals are left.
+fn print_text(out: &mut dyn Write, result: &ScanResult, config: &Config) -> io::Result<()> {
+    if config.summary {
+        writeln!(out, "Went through: {} files", result.files_scanned)?;
+        if result.hardlinks_collapsed > 0 {
+            writeln!(
+                out,
+                "Ignored {} hardlinked copies of files already seen",
+                result.hardlinks_collapsed
+            )?;
+        }
+        if result.files_locked > 0 {
+            writeln!(
+                out,
+                "Skipped {} files locked by other processes",
+                result.files_locked
+            )?;
+        }
+    }
+
+    if !config.stream && !config.quiet {
+        for group in &result.directory_groups {
+            print_directory_group(out, group, config)?;
+        }
+        for group in &result.groups {
+            print_group(out, group, config)?;
+        }
+    }
+
+    if config.summary && result.groups.is_empty() {
+        writeln!(out, "No duplicates found with hash comparison method.")?;
+    } else if config.summary {
+        let duplicate_files: usize = result
+            .groups
+            .iter()
+            .map(|group| group.files.len() - 1)
+            .sum();
+        let reclaimable: u64 = result.groups.iter().map(DuplicateGroup::wasted_bytes).sum();
+        writeln!(
+            out,
+            "Found {} groups of duplicates, {duplicate_files} extra copies, {} reclaimable",
+            result.groups.len(),
+            paint(&format_size(reclaimable), WASTED, config)
+        )?;
+    }
+    if config.summary && config.dirs {
+        let reclaimable: u64 = result
+            .directory_groups
+            .iter()
+            .map(DirectoryGroup::wasted_bytes)
+            .sum();
+        writeln!(
+            out,
+            "Found {} groups of identical directories, {} reclaimable by removing whole directories",
+            result.directory_groups.len(),
+            paint(&format_size(reclaimable), WASTED, config)
+        )?;
+    }
+    if config.summary && result.groups_omitted > 0 {
+        writeln!(
+            out,
+            "Left out {} more groups with less reclaimable space.",
+            result.groups_omitted
+        )?;
+    }
+    match result.stopped {
+        Some(StopReason::Interrupted) => {
+            writeln!(out, "Scan interrupted, the results above are partial.")?
+        }
+        Some(StopReason::MaxFiles) => writeln!(
+            out,
+            "File limit reached, only the first {} files found were compared. The results above are partial.",
+            result.files_scanned
+        )?,
+        Some(StopReason::MaxDuration) => {
+            writeln!(out, "Time limit reached, the results above are partial.")?
+        }
+        None => {}
+    }
+    if config.estimate {
+        writeln!(
+            out,
+            "Estimate only: files were compared by sampled chunks, not their whole contents."
+        )?;
+    }
+    Ok(())
+}
+
+/// Hexadecimal digits of a group identifier.
+const GROUP_ID_LENGTH: usize = 12;
+
+/// Short identifier of `group`, the same from one run to the next as long as its content
+/// is: the start of its digest. With `--same-dir` the directory of the group is mixed in,
+/// since every directory holding copies of the same content gets a group of its own.
+pub fn group_id(group: &DuplicateGroup, config: &Config) -> String {
+    let digest = &group.hash.digest;
+    let directory = group.files[0].path.parent().unwrap_or(Path::new(""));
+    let id = match config.same_dir {
+        true => {
+            let mut hasher = blake3::Hasher::new();
+            hasher.update(group.hash.to_string().as_bytes());
+            hasher.update(b"\0");
+            hasher.update(shown_path(directory, config).as_os_str().as_encoded_bytes());
+            to_hex(hasher.finalize().as_bytes())
+        }
+        false => digest.clone(),
+    };
+    id.chars().take(GROUP_ID_LENGTH).collect()
+}
+
+/// `path` as reported: relative to the scan root it was found under with `--relative`,
+/// as found otherwise.
+pub fn shown_path<'a>(path: &'a Path, config: &Config) -> &'a Path {
+    match config.relative {
+        true => relative_to_root(path, &config.roots),
+        false => path,
+    }
+}
+
+/// `text` wrapped in the ANSI escape sequences of `style` when colors are enabled.
+fn paint(text: &str, style: &str, config: &Config) -> String {
+    match config.color {
+        true => format!("{style}{text}{RESET}"),
+        false => text.to_string(),
+    }
+}
+
+/// Prints the paths of a group as they are, bytes and all, each followed by a NUL byte,
+/// then one more NUL byte to end the group.
+fn print_nul_separated(
+    out: &mut dyn Write,
+    group: &DuplicateGroup,
+    config: &Config,
+) -> io::Result<()> {
+    for file in &group.files {
+        out.write_all(
+            shown_path(&file.path, config)
+                .as_os_str()
+                .as_encoded_bytes(),
+        )?;
+        out.write_all(b"\0")?;
+    }
+    out.write_all(b"\0")
+}
+
+/// Prints a group the way fdupes does, preceded by its size with `--show-size` as
+/// `fdupes -S` would, and followed by a blank line.
+fn print_fdupes_group(
+    out: &mut dyn Write,
+    group: &DuplicateGroup,
+    config: &Config,
+) -> io::Result<()> {
+    if config.show_size {
+        match group.size_range() {
+            (smallest, largest) if smallest != largest => {
+                writeln!(out, "{smallest} to {largest} bytes each:")?
+            }
+            (_, size) => writeln!(out, "{size} byte{} each:", if size == 1 { "" } else { "s" })?,
+        }
+    }
+    for file in &group.files {
+        writeln!(out, "{}", shown_path(&file.path, config).to_string_lossy())?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Prints a line per file of a group, laid out by `--template`.
+fn print_template_group(
+    out: &mut dyn Write,
+    group: &DuplicateGroup,
+    config: &Config,
+) -> io::Result<()> {
+    let Some(template) = &config.template else {
+        return Ok(());
+    };
+    let number = TEMPLATE_GROUPS.fetch_add(1, Ordering::Relaxed) + 1;
+    let id = group_id(group, config);
+    let hash = group.hash.to_string();
+    for file in &group.files {
+        let mut line = String::new();
+        for part in &template.0 {
+            match part {
+                TemplatePart::Literal(text) => line.push_str(text),
+                TemplatePart::Field(field) => {
+                    let (device, inode) = file.file_id.unzip();
+                    let value = match field {
+                        Field::Id => id.clone(),
+                        Field::Hash => hash.clone(),
+                        Field::Size => file.size.to_string(),
+                        Field::Path => shown_path(&file.path, config)
+                            .to_string_lossy()
+                            .into_owned(),
+                        Field::Root => root_of(&file.path, &config.roots)
+                            .map(|root| root.to_string_lossy().into_owned())
+                            .unwrap_or_default(),
+                        Field::Modified => file.modified.map(format_time).unwrap_or_default(),
+                        Field::Group => number.to_string(),
+                        Field::Count => group.files.len().to_string(),
+                        Field::Device => {
+                            device.map(|device| device.to_string()).unwrap_or_default()
+                        }
+                        Field::Inode => inode.map(|inode| inode.to_string()).unwrap_or_default(),
+                    };
+                    line.push_str(&value);
+                }
+            }
+        }
+        writeln!(out, "{line}")?;
+    }
+    Ok(())
+}
+
+/// Prints a summary of the scan, then a table of the files of each group.
+fn print_markdown(out: &mut dyn Write, result: &ScanResult, config: &Config) -> io::Result<()> {
+    let duplicate_files: usize = result
+        .groups
+        .iter()
+        .map(|group| group.files.len() - 1)
+        .sum();
+    let reclaimable: u64 = result.groups.iter().map(DuplicateGroup::wasted_bytes).sum();
+    writeln!(out, "# Duplicate files\n")?;
+    writeln!(out, "## Summary\n")?;
+    writeln!(out, "- Files scanned: {}", result.files_scanned)?;
+    writeln!(out, "- Groups of duplicates: {}", result.groups.len())?;
+    writeln!(out, "- Extra copies: {duplicate_files}")?;
+    writeln!(out, "- Reclaimable space: {}", format_size(reclaimable))?;
+    if result.groups_omitted > 0 {
+        writeln!(
+            out,
+            "- Groups left out, with less reclaimable space: {}",
+            result.groups_omitted
+        )?;
+    }
+    if result.stopped.is_some() {
+        writeln!(out, "- The scan stopped early, these results are partial.")?;
+    }
+
+    if !result.groups.is_empty() {
+        writeln!(out, "\n## Groups")?;
+    }
+    for (index, group) in result.groups.iter().enumerate() {
+        writeln!(
+            out,
+            "\n### Group {} `{}`: {}, {} reclaimable\n",
+            index + 1,
+            group_id(group, config),
+            copies(group),
+            format_size(group.wasted_bytes())
+        )?;
+        writeln!(out, "Hash: `{}`\n", group.hash)?;
+        writeln!(out, "| # | Path | Size | Modified | Inode |")?;
+        writeln!(out, "|---|------|------|----------|-------|")?;
+        for (position, file) in group.files.iter().enumerate() {
+            let modified = file.modified.map(format_time).unwrap_or_default();
+            let inode = file
+                .file_id
+                .map(|(device, inode)| format!("{inode} on device {device}"))
+                .unwrap_or_default();
+            writeln!(
+                out,
+                "| {} | {} | {} | {modified} | {inode} |",
+                position + 1,
+                markdown_escape(&shown_path(&file.path, config).to_string_lossy()),
+                format_size(file.size)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// `text` with the characters Markdown would render as formatting escaped, table
+/// separators included.
+fn markdown_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '|' | '#'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Prints a header, then a row per file of every group, each group known by its
+/// identifier.
+fn print_csv(out: &mut dyn Write, result: &ScanResult, config: &Config) -> io::Result<()> {
+    writeln!(out, "group_id,hash,path,size,mtime")?;
+    for group in &result.groups {
+        let id = group_id(group, config);
+        let hash = group.hash.to_string();
+        for file in &group.files {
+            let modified = file.modified.map(format_time).unwrap_or_default();
+            writeln!(
+                out,
+                "{id},{},{},{},{modified}",
+                csv_field(&hash),
+                csv_field(&shown_path(&file.path, config).to_string_lossy()),
+                file.size
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// `text` as a CSV field, quoted when it holds a separator, a quote or a line break.
+fn csv_field(text: &str) -> String {
+    match text.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", text.replace('"', "\"\"")),
+        false => text.to_string(),
+    }
+}
+
+/// The whole report as a JSON document: the parameters of the scan, every group of
+/// duplicates, and the totals. Each group takes a line of its own.
+fn json_document(result: &ScanResult, config: &Config) -> String {
+    let groups: Vec<String> = result
+        .groups
+        .iter()
+        .map(|group| format!("    {}", json_group(group, config)))
+        .collect();
+    let groups = match groups.is_empty() {
+        true => "[]".to_string(),
+        false => format!("[\n{}\n  ]", groups.join(",\n")),
+    };
+    // Only present when asked for, the document stays the same otherwise.
+    let directories = match config.dirs {
+        true => {
+            let directories: Vec<String> = result
+                .directory_groups
+                .iter()
+                .map(|group| format!("    {}", json_directory_group(group, config)))
+                .collect();
+            match directories.is_empty() {
+                true => ",\n  \"directories\": []".to_string(),
+                false => format!(",\n  \"directories\": [\n{}\n  ]", directories.join(",\n")),
+            }
+        }
+        false => String::new(),
+    };
+    format!(
+        "{{\n  \"parameters\": {},\n  \"groups\": {groups}{directories},\n  \"summary\": {}\n}}",
+        json_parameters(config),
+        json_summary(result, config)
+    )
+}
+
+/// One group of identical directories on a single line.
+fn json_directory_group(group: &DirectoryGroup, config: &Config) -> String {
+    let directories: Vec<String> = group
+        .directories
+        .iter()
+        .map(|directory| json_path(shown_path(directory, config)))
+        .collect();
+    format!(
+        "{{\"directories\": [{}], \"files\": {}, \"size\": {}}}",
+        directories.join(", "),
+        group.files,
+        group.size
+    )
+}
+
+/// Options the scan ran with, those which decide what was found.
+fn json_parameters(config: &Config) -> String {
+    let paths: Vec<String> = config.roots.iter().map(|root| json_path(root)).collect();
+    format!(
+        "{{\"paths\": [{}], \"recurse\": {}, \"include_hidden\": {}, \"algorithm\": {}, \"min_size\": {}, \"max_size\": {}, \"verify\": {}, \"estimate\": {}}}",
+        paths.join(", "),
+        config.recurse,
+        config.include_hidden,
+        json_string(config.algorithm.name()),
+        json_number(config.filters.min_size),
+        json_number(config.filters.max_size),
+        config.verify,
+        config.estimate
+    )
+}
+
+/// One group of duplicates on a single line, its files listed in order.
+fn json_group(group: &DuplicateGroup, config: &Config) -> String {
+    let files: Vec<String> = group
+        .files
+        .iter()
+        .map(|file| json_file(file, config))
+        .collect();
+    format!(
+        "{{\"id\": {}, \"hash\": {}, \"size\": {}, \"files\": [{}]}}",
+        json_string(&group_id(group, config)),
+        json_string(&group.hash.to_string()),
+        group.size_range().1,
+        files.join(", ")
+    )
+}
+
+fn json_file(file: &FileEntry, config: &Config) -> String {
+    let modified = match file.modified {
+        Some(time) => json_string(&format_time(time)),
+        None => "null".to_string(),
+    };
+    let root = match root_of(&file.path, &config.roots) {
+        Some(root) => json_path(root),
+        None => "null".to_string(),
+    };
+    let (device, inode) = file.file_id.unzip();
+    format!(
+        "{{\"path\": {}, \"root\": {root}, \"size\": {}, \"modified\": {modified}, \"device\": {}, \"inode\": {}}}",
+        json_path(shown_path(&file.path, config)),
+        file.size,
+        json_number(device),
+        json_number(inode)
+    )
+}
+
+/// Totals of the scan. Every file of a group but one could be removed, reclaiming its size.
+fn json_summary(result: &ScanResult, config: &Config) -> String {
+    let duplicate_files: usize = result
+        .groups
+        .iter()
+        .map(|group| group.files.len() - 1)
+        .sum();
+    let reclaimable: u64 = result.groups.iter().map(DuplicateGroup::wasted_bytes).sum();
+    let stopped = match result.stopped {
+        Some(StopReason::Interrupted) => json_string("interrupted"),
+        Some(StopReason::MaxFiles) => json_string("max_files"),
+        Some(StopReason::MaxDuration) => json_string("max_duration"),
+        None => "null".to_string(),
+    };
+    format!(
+        "{{\"files_scanned\": {}, \"duplicate_groups\": {}, \"duplicate_files\": {duplicate_files}, \"reclaimable_bytes\": {reclaimable}, \"groups_omitted\": {}, \"hardlinks_collapsed\": {}, \"files_locked\": {}, \"stopped\": {stopped}, \"estimate\": {}}}",
+        result.files_scanned,
+        result.groups.len(),
+        result.groups_omitted,
+        result.hardlinks_collapsed,
+        result.files_locked,
+        config.estimate
+    )
+}
+
+fn json_number(value: Option<u64>) -> String {
+    value.map_or_else(|| "null".to_string(), |value| value.to_string())
+}
+
+/// Paths that are not valid Unicode can not be told apart in JSON, invalid bytes are
+/// replaced as they are when printed as text.
+fn json_path(path: &Path) -> String {
+    json_string(&path.to_string_lossy())
+}
+
+/// `text` as a quoted JSON string, escaped as needed.
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,271 @@
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    ffi::OsString,
+    fs,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::PathBuf,
+    vec,
+};
+
+use crate::{
+    cache::{from_nanos, to_nanos},
+    log,
+    walk::FileEntry,
+};
+
+/// Files found by the walk. They are kept in memory up to a budget, past which they
+/// are written out to disk as runs sorted by size, largest first, merged back once the
+/// walk is over.
+pub struct FileStore {
+    budget: Option<usize>,
+    files: Vec<FileEntry>,
+    /// Estimated memory held by `files`.
+    used: usize,
+    count: usize,
+    /// Temporary directory holding the runs, created on the first spill.
+    directory: Option<PathBuf>,
+    runs: Vec<PathBuf>,
+}
+
+impl FileStore {
+    /// Start an empty store holding at most about `budget` bytes in memory.
+    pub fn new(budget: Option<u64>) -> FileStore {
+        FileStore {
+            budget: budget.map(|budget| budget.try_into().unwrap_or(usize::MAX)),
+            files: Vec::new(),
+            used: 0,
+            count: 0,
+            directory: None,
+            runs: Vec::new(),
+        }
+    }
+
+    pub fn extend(&mut self, files: impl IntoIterator<Item = FileEntry>) {
+        for file in files {
+            self.used += estimated_size(&file);
+            self.count += 1;
+            self.files.push(file);
+        }
+        if self.budget.is_some_and(|budget| self.used > budget)
+            && let Err(err) = self.spill()
+        {
+            // Keep going in memory rather than losing files.
+            log::error!("Error spilling the file list to disk: {err}");
+            self.budget = None;
+        }
+    }
+
+    /// Number of files stored, in memory or on disk.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Files currently held in memory, which is all of them until the budget is exceeded.
+    pub fn in_memory(&self) -> &[FileEntry] {
+        &self.files
+    }
+
+    /// Write the files held in memory to a new run on disk.
+    fn spill(&mut self) -> io::Result<()> {
+        let directory = match &self.directory {
+            Some(directory) => directory.clone(),
+            None => {
+                let directory = std::env::temp_dir().join(format!("fdup-{}", std::process::id()));
+                fs::create_dir_all(&directory)?;
+                self.directory.insert(directory).clone()
+            }
+        };
+        let location = directory.join(format!("run-{}", self.runs.len()));
+        sort_by_size(&mut self.files);
+        let mut writer = BufWriter::new(fs::File::create(&location)?);
+        for file in &self.files {
+            write_entry(&mut writer, file)?;
+        }
+        writer.flush()?;
+        self.runs.push(location);
+        self.files.clear();
+        self.used = 0;
+        Ok(())
+    }
+
+    /// Every file, grouped by size from largest to smallest, sorted by path within a group.
+    pub fn into_size_groups(mut self) -> SizeGroups {
+        sort_by_size(&mut self.files);
+        let mut sources = vec![Source::Memory(std::mem::take(&mut self.files).into_iter())];
+        for run in &self.runs {
+            match fs::File::open(run) {
+                Ok(file) => sources.push(Source::Disk(BufReader::new(file))),
+                Err(err) => log::error!(
+                    "Error reading spilled files: `{}` {err}",
+                    run.to_string_lossy()
+                ),
+            }
+        }
+
+        let mut groups = SizeGroups {
+            sources,
+            heads: Vec::new(),
+            queue: BinaryHeap::new(),
+            directory: self.directory.take(),
+        };
+        for index in 0..groups.sources.len() {
+            groups.heads.push(None);
+            groups.advance(index);
+        }
+        groups
+    }
+}
+
+impl Drop for FileStore {
+    fn drop(&mut self) {
+        if let Some(directory) = &self.directory {
+            let _ = fs::remove_dir_all(directory);
+        }
+    }
+}
+
+/// Where a run of sorted files is read from.
+enum Source {
+    Memory(vec::IntoIter<FileEntry>),
+    Disk(BufReader<fs::File>),
+}
+
+impl Source {
+    fn next(&mut self) -> Option<FileEntry> {
+        match self {
+            Source::Memory(files) => files.next(),
+            Source::Disk(reader) => match read_entry(reader) {
+                Ok(file) => file,
+                Err(err) => {
+                    log::error!("Error reading spilled files: {err}");
+                    None
+                }
+            },
+        }
+    }
+}
+
+/// Merge of every sorted run, yielding one group of files per size.
+/// The temporary runs are deleted once the merge is dropped.
+pub struct SizeGroups {
+    sources: Vec<Source>,
+    /// Next file of each source, waiting for its turn in `queue`.
+    heads: Vec<Option<FileEntry>>,
+    /// Smallest key on top: largest size, then first path.
+    queue: BinaryHeap<Reverse<(Reverse<u64>, PathBuf, usize)>>,
+    directory: Option<PathBuf>,
+}
+
+impl SizeGroups {
+    /// Load the next file of source `index` into the queue.
+    fn advance(&mut self, index: usize) {
+        if let Some(file) = self.sources[index].next() {
+            self.queue
+                .push(Reverse((Reverse(file.size), file.path.clone(), index)));
+            self.heads[index] = Some(file);
+        }
+    }
+
+    fn pop(&mut self) -> Option<FileEntry> {
+        let Reverse((_, _, index)) = self.queue.pop()?;
+        let file = self.heads[index].take();
+        self.advance(index);
+        file
+    }
+}
+
+impl Iterator for SizeGroups {
+    type Item = Vec<FileEntry>;
+
+    fn next(&mut self) -> Option<Vec<FileEntry>> {
+        let mut group = vec![self.pop()?];
+        while let Some(Reverse((Reverse(size), _, _))) = self.queue.peek()
+            && *size == group[0].size
+        {
+            group.extend(self.pop());
+        }
+        Some(group)
+    }
+}
+
+impl Drop for SizeGroups {
+    fn drop(&mut self) {
+        if let Some(directory) = &self.directory {
+            let _ = fs::remove_dir_all(directory);
+        }
+    }
+}
+
+/// Rough amount of memory taken by `file`.
+pub fn estimated_size(file: &FileEntry) -> usize {
+    size_of::<FileEntry>() + file.path.as_os_str().len()
+}
+
+/// Largest files first, by path among files of the same size.
+fn sort_by_size(files: &mut [FileEntry]) {
+    files.sort_by(|a, b| (Reverse(a.size), &a.path).cmp(&(Reverse(b.size), &b.path)));
+}
+
+/// Append `file` to a run: size, optional mtime, optional file id, then the path.
+fn write_entry(writer: &mut impl Write, file: &FileEntry) -> io::Result<()> {
+    writer.write_all(&file.size.to_le_bytes())?;
+    match file.modified.and_then(to_nanos) {
+        Some(nanos) => {
+            writer.write_all(&[1])?;
+            writer.write_all(&nanos.to_le_bytes())?;
+        }
+        None => writer.write_all(&[0])?,
+    }
+    match file.file_id {
+        Some((device, inode)) => {
+            writer.write_all(&[1])?;
+            writer.write_all(&device.to_le_bytes())?;
+            writer.write_all(&inode.to_le_bytes())?;
+        }
+        None => writer.write_all(&[0])?,
+    }
+    let path = file.path.as_os_str().as_encoded_bytes();
+    writer.write_all(&(path.len() as u64).to_le_bytes())?;
+    writer.write_all(path)
+}
+
+/// Read the next file of a run, `None` at its end.
+fn read_entry(reader: &mut impl Read) -> io::Result<Option<FileEntry>> {
+    let mut size = [0; 8];
+    match reader.read_exact(&mut size) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    let modified = match read_array::<1>(reader)? {
+        [0] => None,
+        _ => Some(from_nanos(u128::from_le_bytes(read_array(reader)?))),
+    };
+    let file_id = match read_array::<1>(reader)? {
+        [0] => None,
+        _ => Some((
+            u64::from_le_bytes(read_array(reader)?),
+            u64::from_le_bytes(read_array(reader)?),
+        )),
+    };
+    let length = u64::from_le_bytes(read_array(reader)?);
+    let mut path = vec![0; length.try_into().unwrap_or(usize::MAX)];
+    reader.read_exact(&mut path)?;
+    // SAFETY: the bytes come from `as_encoded_bytes` in `write_entry`, written by this
+    // very process to a file nobody else is meant to touch.
+    let path = unsafe { OsString::from_encoded_bytes_unchecked(path) };
+    Ok(Some(FileEntry {
+        path: PathBuf::from(path),
+        size: u64::from_le_bytes(size),
+        modified,
+        file_id,
+        known_hash: None,
+    }))
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
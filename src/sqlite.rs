@@ -0,0 +1,77 @@
+use std::path::Path;
+
+use rusqlite::{Connection, params};
+
+use crate::{
+    ScanResult,
+    config::Config,
+    report::{group_id, shown_path},
+    units::format_time,
+};
+
+/// Tables the results are written to, replacing those of a previous export.
+/// `directory_duplicates` answers the common question of where duplicates pile up.
+const SCHEMA: &str = "
+DROP VIEW IF EXISTS directory_duplicates;
+DROP TABLE IF EXISTS files;
+DROP TABLE IF EXISTS groups;
+CREATE TABLE groups (
+    id INTEGER PRIMARY KEY,
+    stable_id TEXT NOT NULL,
+    hash TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    copies INTEGER NOT NULL,
+    wasted INTEGER NOT NULL
+);
+CREATE TABLE files (
+    id INTEGER PRIMARY KEY,
+    group_id INTEGER NOT NULL REFERENCES groups (id),
+    path TEXT NOT NULL,
+    directory TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    modified TEXT
+);
+CREATE INDEX files_group ON files (group_id);
+CREATE VIEW directory_duplicates AS
+    SELECT directory, COUNT(*) AS files, SUM(size) AS bytes
+    FROM files GROUP BY directory ORDER BY bytes DESC;
+";
+
+/// Write every group of duplicates and the files it holds to the SQLite database at
+/// `location`, all at once so a failed export leaves the previous one untouched.
+pub fn export(location: &Path, result: &ScanResult, config: &Config) -> rusqlite::Result<()> {
+    let mut connection = Connection::open(location)?;
+    let transaction = connection.transaction()?;
+    transaction.execute_batch(SCHEMA)?;
+    {
+        let mut insert_group = transaction.prepare(
+            "INSERT INTO groups (id, stable_id, hash, size, copies, wasted) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?;
+        let mut insert_file = transaction.prepare(
+            "INSERT INTO files (group_id, path, directory, size, modified) VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        for (index, group) in result.groups.iter().enumerate() {
+            let id = index + 1;
+            insert_group.execute(params![
+                id,
+                group_id(group, config),
+                group.hash.to_string(),
+                group.size_range().1,
+                group.files.len(),
+                group.wasted_bytes()
+            ])?;
+            for file in &group.files {
+                let path = shown_path(&file.path, config);
+                let directory = path.parent().unwrap_or(Path::new(""));
+                insert_file.execute(params![
+                    id,
+                    path.to_string_lossy(),
+                    directory.to_string_lossy(),
+                    file.size,
+                    file.modified.map(format_time)
+                ])?;
+            }
+        }
+    }
+    transaction.commit()
+}
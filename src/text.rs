@@ -0,0 +1,154 @@
+use std::{collections::HashMap, fs, io, path::Path, str::FromStr};
+
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::{
+    DuplicateGroup,
+    config::Config,
+    hash::get_parts_hash,
+    media, open_files,
+    progress::{PROGRESS, Phase},
+    walk::FileEntry,
+};
+
+/// Percentage of shared shingles two documents need to be deemed near duplicates,
+/// unless told otherwise.
+pub const DEFAULT_SIMILARITY: u32 = 90;
+
+/// Words per shingle, the overlapping runs of words documents are compared by.
+const SHINGLE_WORDS: usize = 5;
+
+/// Minimum hashes kept per document, each under a different permutation of shingles.
+const PERMUTATIONS: usize = 128;
+
+/// Minimum hashes per band. Documents sharing a whole band are compared, which finds
+/// near duplicates without comparing every document with every other one.
+const BAND_SIZE: usize = 4;
+
+/// Files larger than this are not read as text, they are unlikely to be documents.
+const MAX_TEXT_SIZE: u64 = 64 * 1024 * 1024;
+
+/// How text files are compared by `--text`, instead of by their bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextMode {
+    /// Grouped when most of their wording is the same, such as revised copies.
+    Similar,
+}
+
+impl FromStr for TextMode {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "similar" => Ok(TextMode::Similar),
+            _ => Err(format!("Unknown text mode `{name}`, expected: similar")),
+        }
+    }
+}
+
+/// MinHash signature of the text file at `path`: the smallest hash of its shingles
+/// under every permutation. The share of minimums two signatures agree on estimates
+/// the share of shingles their documents have in common. `None` for files that are not
+/// text, told by a NUL byte, and for those holding no words.
+fn signature(path: &Path, size: u64) -> io::Result<Option<Vec<u64>>> {
+    if size > MAX_TEXT_SIZE {
+        return Ok(None);
+    }
+    let bytes = {
+        let _permit = open_files::acquire(1);
+        fs::read(path)?
+    };
+    if bytes.contains(&0) {
+        return Ok(None);
+    }
+    // Case and punctuation are left out, reflowed or retyped copies still match.
+    let text = String::from_utf8_lossy(&bytes).to_lowercase();
+    let words: Vec<&str> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect();
+    if words.is_empty() {
+        return Ok(None);
+    }
+    let mut minimums = vec![u64::MAX; PERMUTATIONS];
+    for shingle in words.windows(SHINGLE_WORDS.min(words.len())) {
+        let hash = xxh3_64(shingle.join(" ").as_bytes());
+        for (permutation, minimum) in minimums.iter_mut().enumerate() {
+            *minimum = (*minimum).min(permute(hash, permutation as u64));
+        }
+    }
+    Ok(Some(minimums))
+}
+
+/// `hash` scrambled by the `permutation`th of a family of mixing functions (SplitMix64).
+fn permute(hash: u64, permutation: u64) -> u64 {
+    let mut value = hash.wrapping_add((permutation + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    value = (value ^ (value >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    value ^ (value >> 31)
+}
+
+/// Percentage of the minimum hashes signatures `a` and `b` agree on.
+fn similarity(a: &[u64], b: &[u64]) -> u32 {
+    let agreeing = a.iter().zip(b).filter(|(a, b)| a == b).count();
+    (agreeing * 100 / PERMUTATIONS) as u32
+}
+
+/// Groups of text files among `files` that are near duplicates: whose shingles are
+/// estimated to be at least `config.text_similarity` percent the same, directly or
+/// through other documents of the group. Files that are not text are left out.
+pub fn find_similar(files: Vec<FileEntry>, config: &Config) -> Vec<DuplicateGroup> {
+    PROGRESS.start_phase(Phase::Hashing, files.iter().map(|file| file.size).sum());
+    let signatures = media::key_each(&files, config.threads, |file| {
+        signature(&file.path, file.size)
+    });
+    let mut parents: Vec<usize> = (0..signatures.len()).collect();
+    let mut bands: HashMap<(usize, &[u64]), Vec<usize>> = HashMap::new();
+    for (document, (_, minimums)) in signatures.iter().enumerate() {
+        for (band, values) in minimums.chunks(BAND_SIZE).enumerate() {
+            bands.entry((band, values)).or_default().push(document);
+        }
+    }
+    for documents in bands.values().filter(|documents| documents.len() > 1) {
+        for (position, &document) in documents.iter().enumerate() {
+            for &other in &documents[position + 1..] {
+                let (first, second) = (
+                    media::find_set(&mut parents, document),
+                    media::find_set(&mut parents, other),
+                );
+                // Documents sharing several bands only need comparing once.
+                if first == second {
+                    continue;
+                }
+                if similarity(&signatures[document].1, &signatures[other].1)
+                    >= config.text_similarity
+                {
+                    parents[first.max(second)] = first.min(second);
+                }
+            }
+        }
+    }
+    let mut members: Vec<Vec<usize>> = vec![Vec::new(); signatures.len()];
+    for document in 0..signatures.len() {
+        let root = media::find_set(&mut parents, document);
+        members[root].push(document);
+    }
+    members
+        .into_iter()
+        .filter(|documents| documents.len() > 1)
+        .map(|documents| {
+            let bytes: Vec<u8> = signatures[documents[0]]
+                .1
+                .iter()
+                .flat_map(|minimum| minimum.to_le_bytes())
+                .collect();
+            DuplicateGroup {
+                hash: get_parts_hash(&[&bytes], config.algorithm),
+                files: documents
+                    .iter()
+                    .map(|&document| files[signatures[document].0].clone())
+                    .collect(),
+            }
+        })
+        .collect()
+}
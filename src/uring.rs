@@ -0,0 +1,192 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    fs::File,
+    io,
+    os::fd::AsRawFd,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use io_uring::{IoUring, opcode, types};
+
+use crate::log;
+
+/// Reads kept in flight at once for a single file.
+const QUEUE_DEPTH: usize = 8;
+
+thread_local! {
+    /// Each hashing thread sets up its own ring on first use.
+    static RING: RefCell<Option<IoUring>> = const { RefCell::new(None) };
+}
+
+/// Set once setting up a ring failed, the kernel or a sandbox does not allow io_uring.
+static UNAVAILABLE: AtomicBool = AtomicBool::new(false);
+
+/// Part of the file read into one of the buffers.
+struct Slot {
+    offset: u64,
+    /// Range of the buffer still being filled, and handed over once complete.
+    start: usize,
+    end: usize,
+    result: Option<i32>,
+}
+
+/// Read the first `size` bytes of `file` through io_uring, keeping up to `QUEUE_DEPTH`
+/// reads of `chunk_size` bytes in flight, and hand them to `consume` in order.
+/// Returns `false` without reading anything when io_uring is not available, the file
+/// then has to be read the usual way.
+pub fn read_file(
+    file: &File,
+    size: u64,
+    chunk_size: usize,
+    consume: impl FnMut(&[u8]) -> io::Result<()>,
+) -> io::Result<bool> {
+    if UNAVAILABLE.load(Ordering::Relaxed) {
+        return Ok(false);
+    }
+    RING.with_borrow_mut(|ring| {
+        let ring = match ring {
+            Some(ring) => ring,
+            None => match IoUring::new(QUEUE_DEPTH as u32) {
+                Ok(created) => ring.insert(created),
+                Err(err) => {
+                    if !UNAVAILABLE.swap(true, Ordering::Relaxed) {
+                        log::warn!("io_uring is not available, reading files normally: {err}");
+                    }
+                    return Ok(false);
+                }
+            },
+        };
+        let mut reader = Reader {
+            ring,
+            fd: types::Fd(file.as_raw_fd()),
+            buffers: (0..QUEUE_DEPTH)
+                .map(|_| vec![0; chunk_size.max(1)])
+                .collect(),
+            slots: (0..QUEUE_DEPTH).map(|_| None).collect(),
+            in_flight: 0,
+        };
+        let result = reader.read(size, consume);
+        // The kernel may still be writing into the buffers, they must outlive every read.
+        reader.drain();
+        result.map(|_| true)
+    })
+}
+
+struct Reader<'a> {
+    ring: &'a mut IoUring,
+    fd: types::Fd,
+    buffers: Vec<Vec<u8>>,
+    slots: Vec<Option<Slot>>,
+    in_flight: usize,
+}
+
+impl Reader<'_> {
+    fn read(
+        &mut self,
+        size: u64,
+        mut consume: impl FnMut(&[u8]) -> io::Result<()>,
+    ) -> io::Result<()> {
+        // Slots in file order, the first one is always the next to hand over.
+        let mut order = VecDeque::new();
+        let mut next_offset = 0;
+        loop {
+            while order.len() < QUEUE_DEPTH && next_offset < size {
+                let index = (0..QUEUE_DEPTH)
+                    .find(|&index| self.slots[index].is_none())
+                    .expect("a slot is free while the queue is not full");
+                let length = self.buffers[index]
+                    .len()
+                    .min((size - next_offset).try_into().unwrap_or(usize::MAX));
+                self.slots[index] = Some(Slot {
+                    offset: next_offset,
+                    start: 0,
+                    end: length,
+                    result: None,
+                });
+                self.submit(index)?;
+                order.push_back(index);
+                next_offset += length as u64;
+            }
+            let Some(&first) = order.front() else {
+                return Ok(());
+            };
+
+            match self.ring.submit_and_wait(1) {
+                Ok(_) => {}
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+            for completion in self.ring.completion() {
+                self.in_flight -= 1;
+                if let Some(slot) = &mut self.slots[completion.user_data() as usize] {
+                    slot.result = Some(completion.result());
+                }
+            }
+
+            // Hand over finished chunks in order, later ones wait for those before them.
+            let mut index = first;
+            while let Some(slot) = &mut self.slots[index]
+                && let Some(result) = slot.result.take()
+            {
+                let read = match result {
+                    0 => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "file shrank while being read",
+                        ));
+                    }
+                    read if read < 0 => return Err(io::Error::from_raw_os_error(-read)),
+                    read => read as usize,
+                };
+                let start = slot.start;
+                slot.start += read;
+                slot.offset += read as u64;
+                consume(&self.buffers[index][start..start + read])?;
+
+                // Short reads are resumed right away, still ahead of every other slot.
+                if let Some(slot) = &self.slots[index]
+                    && slot.start < slot.end
+                {
+                    self.submit(index)?;
+                    break;
+                }
+                self.slots[index] = None;
+                order.pop_front();
+                match order.front() {
+                    Some(&next) => index = next,
+                    None => break,
+                }
+            }
+        }
+    }
+
+    /// Queue the read filling what is left of slot `index`.
+    fn submit(&mut self, index: usize) -> io::Result<()> {
+        let slot = self.slots[index]
+            .as_ref()
+            .expect("only used slots are read into");
+        let buffer = &mut self.buffers[index][slot.start..slot.end];
+        let entry = opcode::Read::new(self.fd, buffer.as_mut_ptr(), buffer.len() as u32)
+            .offset(slot.offset)
+            .build()
+            .user_data(index as u64);
+        // SAFETY: the buffer lives in `self.buffers`, which is never resized and outlives
+        // the read since `drain` waits for every read before the reader goes away.
+        unsafe { self.ring.submission().push(&entry) }.map_err(io::Error::other)?;
+        self.in_flight += 1;
+        Ok(())
+    }
+
+    /// Wait for every read still in flight.
+    fn drain(&mut self) {
+        while self.in_flight > 0 {
+            match self.ring.submit_and_wait(self.in_flight) {
+                Ok(_) => {}
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(_) => break,
+            }
+            self.in_flight -= self.ring.completion().count();
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use std::{
+    fs::File,
+    io,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+};
+
+use crate::{
+    DuplicateGroup, devices,
+    hash::{BufferSize, read_full},
+    interrupt, log, open_files,
+    progress::PROGRESS,
+    walk::FileEntry,
+};
+
+/// Confirm every group by comparing the contents of its files byte by byte.
+/// Groups are verified in parallel by `threads` threads. A group whose files turn out to differ despite
+/// sharing a hash is split up, and files left without a twin are dropped. Groups not
+/// verified yet when the scan is interrupted are dropped too.
+pub fn verify_groups(
+    groups: Vec<DuplicateGroup>,
+    buffer_size: BufferSize,
+    threads: usize,
+) -> Vec<DuplicateGroup> {
+    let mut splits: Vec<Vec<Vec<usize>>> = vec![Vec::new(); groups.len()];
+    let worker_count = threads.min(groups.len()).max(1);
+    let next_group = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..worker_count {
+            let sender = sender.clone();
+            let (groups, next_group) = (&groups, &next_group);
+            scope.spawn(move || {
+                while !interrupt::interrupted() {
+                    let index = next_group.fetch_add(1, Ordering::Relaxed);
+                    let Some(group) = groups.get(index) else {
+                        break;
+                    };
+                    if sender
+                        .send((index, identical_sets(&group.files, buffer_size)))
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+        }
+        // Only the workers hold senders now, the loop ends once all of them finish.
+        drop(sender);
+
+        for (index, sets) in receiver {
+            splits[index] = sets;
+        }
+    });
+
+    groups
+        .into_iter()
+        .zip(splits)
+        .flat_map(|(group, sets)| split_group(group, sets))
+        .collect()
+}
+
+/// Confirm a single group by comparing its files byte by byte.
+pub fn verify_group(group: DuplicateGroup, buffer_size: BufferSize) -> Vec<DuplicateGroup> {
+    let sets = identical_sets(&group.files, buffer_size);
+    split_group(group, sets)
+}
+
+/// Break `group` up into one group per set of identical file indexes.
+fn split_group(group: DuplicateGroup, sets: Vec<Vec<usize>>) -> Vec<DuplicateGroup> {
+    if sets.len() > 1 {
+        log::warn!(
+            "Files sharing hash {} differ in content, reporting them separately",
+            group.hash
+        );
+    }
+    let mut files: Vec<_> = group.files.into_iter().map(Some).collect();
+    sets.into_iter()
+        .map(|set| DuplicateGroup {
+            hash: group.hash.clone(),
+            files: set
+                .into_iter()
+                .filter_map(|index| files[index].take())
+                .collect(),
+        })
+        .collect()
+}
+
+/// Indexes of `files` holding the exact same content, as sets of two or more.
+/// Each file is compared against the first member of every set found so far.
+fn identical_sets(files: &[FileEntry], buffer_size: BufferSize) -> Vec<Vec<usize>> {
+    let mut sets: Vec<Vec<usize>> = Vec::new();
+    'files: for (index, file) in files.iter().enumerate() {
+        for set in &mut sets {
+            let representative = &files[set[0]];
+            let _permit = devices::acquire(representative.file_id.map(|(device, _)| device));
+            match files_equal(&representative.path, &file.path, buffer_size) {
+                Ok(true) => {
+                    set.push(index);
+                    continue 'files;
+                }
+                Ok(false) => {}
+                Err(err) => {
+                    log::error!(
+                        "Error comparing files: `{}` and `{}` {err}",
+                        representative.path.to_string_lossy(),
+                        file.path.to_string_lossy()
+                    );
+                    continue 'files;
+                }
+            }
+        }
+        sets.push(vec![index]);
+    }
+    sets.retain(|set| set.len() > 1);
+    sets
+}
+
+/// Whether two files hold exactly the same bytes, read in chunks sized by `buffer_size`.
+pub fn files_equal(first: &Path, second: &Path, buffer_size: BufferSize) -> io::Result<bool> {
+    let _permit = open_files::acquire(2);
+    let mut first = File::open(first)?;
+    let mut second = File::open(second)?;
+    let meta = first.metadata()?;
+    if meta.len() != second.metadata()?.len() {
+        return Ok(false);
+    }
+
+    let chunk_size = buffer_size.chunk_size(&first, &meta).max(1);
+    let mut first_buffer = vec![0; chunk_size];
+    let mut second_buffer = vec![0; chunk_size];
+    loop {
+        let first_read = read_full(&mut first, &mut first_buffer)?;
+        let second_read = read_full(&mut second, &mut second_buffer)?;
+        PROGRESS.add_bytes((first_read + second_read) as u64);
+        if first_buffer[..first_read] != second_buffer[..second_read] {
+            return Ok(false);
+        }
+        if first_read == 0 {
+            return Ok(true);
+        }
+    }
+}
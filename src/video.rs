@@ -0,0 +1,206 @@
+use std::{
+    ffi::OsStr,
+    io,
+    path::Path,
+    process::{Command, Stdio},
+    str::FromStr,
+};
+
+use crate::{
+    DuplicateGroup,
+    config::Config,
+    hash::get_parts_hash,
+    images, log,
+    magic::{self, FileKind},
+    media, open_files,
+    progress::{PROGRESS, Phase},
+    walk::FileEntry,
+};
+
+/// Frames sampled from every video, spread evenly between its start and end.
+const SAMPLES: u32 = 8;
+
+/// Bits the perceptual hashes of sampled frames may differ by on average for their
+/// videos to be deemed the same.
+const MAX_DISTANCE: u32 = 10;
+
+/// Seconds two videos may differ by in length and still be compared, on top of one
+/// percent of the longest, for the frames trimmed or added by exports.
+const LENGTH_TOLERANCE: f64 = 1.0;
+
+/// How videos are compared by `--video`, instead of by their bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VideoMode {
+    /// Grouped when they show the same, whatever their container or resolution.
+    Similar,
+}
+
+impl FromStr for VideoMode {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "similar" => Ok(VideoMode::Similar),
+            _ => Err(format!("Unknown video mode `{name}`, expected: similar")),
+        }
+    }
+}
+
+/// What a video looks like, as far as comparing it goes.
+struct Signature {
+    /// Length in seconds.
+    duration: f64,
+    /// Difference hash of every sampled frame.
+    frames: Vec<u64>,
+}
+
+/// Run `program` with `args` and return what it printed, failing when it does.
+fn run(program: &str, args: &[&OsStr]) -> io::Result<Vec<u8>> {
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!(
+            "{program} failed: {}",
+            message.trim()
+        )));
+    }
+    Ok(output.stdout)
+}
+
+/// Length and sampled frames of the video at `path`, as decoded by ffmpeg. Frames are
+/// shrunk to 9 by 8 gray pixels by ffmpeg itself, so only their hashes come back.
+fn signature(path: &Path) -> io::Result<Signature> {
+    let _permit = open_files::acquire(1);
+    let output = run(
+        "ffprobe",
+        &[
+            "-v".as_ref(),
+            "error".as_ref(),
+            "-show_entries".as_ref(),
+            "format=duration".as_ref(),
+            "-of".as_ref(),
+            "default=noprint_wrappers=1:nokey=1".as_ref(),
+            path.as_os_str(),
+        ],
+    )?;
+    let duration = String::from_utf8_lossy(&output)
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|duration| duration.is_finite() && *duration > 0.0)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "unknown duration"))?;
+    let mut frames = Vec::with_capacity(SAMPLES as usize);
+    for sample in 1..=SAMPLES {
+        let at = format!(
+            "{:.3}",
+            duration * f64::from(sample) / f64::from(SAMPLES + 1)
+        );
+        let pixels = run(
+            "ffmpeg",
+            &[
+                "-v".as_ref(),
+                "error".as_ref(),
+                "-ss".as_ref(),
+                at.as_ref(),
+                "-i".as_ref(),
+                path.as_os_str(),
+                "-frames:v".as_ref(),
+                "1".as_ref(),
+                "-vf".as_ref(),
+                "scale=9:8:flags=area,format=gray".as_ref(),
+                "-f".as_ref(),
+                "rawvideo".as_ref(),
+                "-".as_ref(),
+            ],
+        )?;
+        if pixels.len() != 9 * 8 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("no frame at {at} seconds"),
+            ));
+        }
+        frames.push(images::difference_bits(&pixels));
+    }
+    Ok(Signature { duration, frames })
+}
+
+/// Whether videos with signatures `a` and `b` show the same.
+fn same_content(a: &Signature, b: &Signature) -> bool {
+    let distance: u32 = a
+        .frames
+        .iter()
+        .zip(&b.frames)
+        .map(|(a, b)| (a ^ b).count_ones())
+        .sum();
+    distance <= MAX_DISTANCE * SAMPLES
+}
+
+/// Groups of videos among `files` showing the same content, told by the perceptual
+/// hashes of frames sampled all along them: the same clip in different containers or
+/// resolutions. Only videos about as long as each other are compared. Files that are
+/// not videos are left out, and so is every file when ffmpeg is not installed.
+pub fn find_similar(files: Vec<FileEntry>, config: &Config) -> Vec<DuplicateGroup> {
+    if let Err(err) = run("ffmpeg", &["-version".as_ref()]) {
+        log::error!("Error running ffmpeg, needed to compare videos: {err}");
+        return Vec::new();
+    }
+    PROGRESS.start_phase(Phase::Hashing, files.iter().map(|file| file.size).sum());
+    let signatures = media::key_each(&files, config.threads, |file| {
+        match magic::sniff(&file.path)? {
+            Some(FileKind::Video) => signature(&file.path).map(Some),
+            _ => Ok(None),
+        }
+    });
+    // Videos as long as each other sit next to each other, only those are compared.
+    let mut order: Vec<usize> = (0..signatures.len()).collect();
+    order.sort_by(|&a, &b| {
+        signatures[a]
+            .1
+            .duration
+            .total_cmp(&signatures[b].1.duration)
+    });
+    let mut parents: Vec<usize> = (0..signatures.len()).collect();
+    for (position, &video) in order.iter().enumerate() {
+        let duration = signatures[video].1.duration;
+        for &other in &order[position + 1..] {
+            let longer = signatures[other].1.duration;
+            if longer - duration > LENGTH_TOLERANCE + longer / 100.0 {
+                break;
+            }
+            if same_content(&signatures[video].1, &signatures[other].1) {
+                let (first, second) = (
+                    media::find_set(&mut parents, video),
+                    media::find_set(&mut parents, other),
+                );
+                parents[first.max(second)] = first.min(second);
+            }
+        }
+    }
+    let mut members: Vec<Vec<usize>> = vec![Vec::new(); signatures.len()];
+    for video in 0..signatures.len() {
+        let root = media::find_set(&mut parents, video);
+        members[root].push(video);
+    }
+    members
+        .into_iter()
+        .filter(|videos| videos.len() > 1)
+        .map(|videos| {
+            let bytes: Vec<u8> = signatures[videos[0]]
+                .1
+                .frames
+                .iter()
+                .flat_map(|frame| frame.to_le_bytes())
+                .collect();
+            DuplicateGroup {
+                hash: get_parts_hash(&[&bytes], config.algorithm),
+                files: videos
+                    .iter()
+                    .map(|&video| files[signatures[video].0].clone())
+                    .collect(),
+            }
+        })
+        .collect()
+}
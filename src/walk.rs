@@ -0,0 +1,518 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    fs::{self, Metadata},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::{
+        Condvar, Mutex, PoisonError,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    thread,
+    time::SystemTime,
+};
+
+use crate::{
+    checkpoint::Checkpoint, config::Config, hash::FileHash, ignore_files::IgnoreRules, interrupt,
+    log, open_files, platform, progress::PROGRESS, spill::FileStore,
+};
+
+/// A file found while walking, along with the metadata needed to compare it.
+#[derive(Clone)]
+pub struct FileEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    /// `(device, inode)` of the file where available, shared by all its hardlinks. Only
+    /// known once `identify` was called where the metadata does not hold it.
+    pub file_id: Option<(u64, u64)>,
+    /// Full hash recorded by a previous incremental scan, for files unchanged since.
+    pub known_hash: Option<FileHash>,
+}
+
+impl FileEntry {
+    /// The file at `path`, described by its metadata `meta`.
+    fn new(path: PathBuf, meta: &Metadata) -> FileEntry {
+        FileEntry {
+            path,
+            size: meta.len(),
+            modified: meta.modified().ok(),
+            file_id: platform::file_id(meta),
+            known_hash: None,
+        }
+    }
+
+    /// Look up `file_id` when the metadata did not hold it. This opens the file on
+    /// Windows, so it is only done for files the identity is needed for.
+    pub fn identify(&mut self) {
+        if self.file_id.is_none() {
+            let _permit = open_files::acquire(1);
+            self.file_id = platform::find_file_id(&self.path);
+        }
+    }
+}
+
+/// Directories waiting to be visited and files found so far, shared by all walker threads.
+struct WorkQueue {
+    state: Mutex<QueueState>,
+    changed: Condvar,
+    budget: FileBudget,
+}
+
+struct QueueState {
+    directories: VecDeque<PathBuf>,
+    /// Directories currently being read. The walk is over once the queue is empty
+    /// and nothing is being read, since only busy walkers can queue more work.
+    in_progress: Vec<PathBuf>,
+    files: FileStore,
+}
+
+impl WorkQueue {
+    /// Wait for the next directory to visit, `None` once the walk is complete or interrupted.
+    fn next(&self) -> Option<PathBuf> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        loop {
+            if interrupt::interrupted() || self.budget.exceeded() {
+                return None;
+            }
+            // Whatever is left to read would go over the budget.
+            if self.budget.spent() && !state.directories.is_empty() {
+                self.budget.exceed();
+                return None;
+            }
+            if let Some(directory) = state.directories.pop_front() {
+                state.in_progress.push(directory.clone());
+                return Some(directory);
+            }
+            if state.in_progress.is_empty() {
+                return None;
+            }
+            state = self
+                .changed
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// Mark the visit of `directory` as done, queueing the directories and keeping the
+    /// files found inside.
+    fn finish(&self, directory: &Path, subdirectories: Vec<PathBuf>, files: Vec<FileEntry>) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.directories.extend(subdirectories);
+        state.files.extend(files);
+        state.in_progress.retain(|visiting| visiting != directory);
+        self.changed.notify_all();
+    }
+
+    /// Put `directory` back in the queue, its visit was cut short.
+    fn abandon(&self, directory: PathBuf) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.in_progress.retain(|visiting| *visiting != directory);
+        state.directories.push_front(directory);
+        self.changed.notify_all();
+    }
+
+    /// Directories left to visit, including those being read, and files found so far.
+    fn snapshot(&self) -> (Vec<PathBuf>, Vec<FileEntry>) {
+        let state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let pending = state
+            .directories
+            .iter()
+            .chain(&state.in_progress)
+            .cloned()
+            .collect();
+        (pending, state.files.in_memory().to_vec())
+    }
+}
+
+/// Files the walk may still find, when limited by `--max-files`.
+struct FileBudget {
+    left: Option<AtomicU64>,
+    /// Set once files had to be left out.
+    exceeded: AtomicBool,
+}
+
+impl FileBudget {
+    fn new(max_files: Option<u64>) -> FileBudget {
+        FileBudget {
+            left: max_files.map(AtomicU64::new),
+            exceeded: AtomicBool::new(false),
+        }
+    }
+
+    /// Take one file out of the budget, `false` when it is spent and the file must be
+    /// left out.
+    fn take(&self) -> bool {
+        let Some(left) = &self.left else {
+            return true;
+        };
+        let taken = left
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| {
+                left.checked_sub(1)
+            })
+            .is_ok();
+        if !taken {
+            self.exceed();
+        }
+        taken
+    }
+
+    fn spent(&self) -> bool {
+        self.left
+            .as_ref()
+            .is_some_and(|left| left.load(Ordering::Relaxed) == 0)
+    }
+
+    fn exceed(&self) {
+        self.exceeded.store(true, Ordering::Relaxed);
+    }
+
+    fn exceeded(&self) -> bool {
+        self.exceeded.load(Ordering::Relaxed)
+    }
+}
+
+/// Directories walked so far when following symlinks, so that none is walked twice.
+/// Tracking every directory, not only those behind a link, is what breaks loops: a link
+/// back to an ancestor leads to a directory already seen.
+struct VisitedDirectories {
+    seen: Mutex<HashSet<DirectoryId>>,
+}
+
+/// Identity of a directory: `(device, inode)` where available, its canonical path otherwise,
+/// which resolves junctions on Windows as well.
+#[derive(PartialEq, Eq, Hash)]
+enum DirectoryId {
+    File(u64, u64),
+    Path(PathBuf),
+}
+
+impl VisitedDirectories {
+    fn new() -> VisitedDirectories {
+        VisitedDirectories {
+            seen: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Record the directory at `path`, `false` when it was seen already.
+    fn first_visit(&self, path: &Path, meta: &Metadata) -> bool {
+        let id = match platform::file_id(meta).or_else(|| platform::find_file_id(path)) {
+            Some((device, inode)) => DirectoryId::File(device, inode),
+            None => match std::fs::canonicalize(path) {
+                Ok(path) => DirectoryId::Path(path),
+                Err(_) => return false,
+            },
+        };
+        self.seen
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(id)
+    }
+}
+
+/// Canonical form of every root not already covered by another one: roots nested
+/// inside or equal to another root are dropped, their files are found from it already.
+pub fn distinct_roots(roots: &[PathBuf]) -> Vec<PathBuf> {
+    let canonical: Vec<PathBuf> = roots
+        .iter()
+        .map(|root| std::fs::canonicalize(root).unwrap_or_else(|_| root.clone()))
+        .collect();
+    let mut distinct: Vec<PathBuf> = Vec::new();
+    for (index, root) in canonical.iter().enumerate() {
+        let covered = canonical.iter().enumerate().any(|(other, covering)| {
+            other != index && root.starts_with(covering) && (root != covering || other < index)
+        });
+        if covered {
+            log::warn!(
+                "Skipping `{}`, it is already covered by another path",
+                roots[index].to_string_lossy()
+            );
+        } else {
+            distinct.push(roots[index].clone());
+        }
+    }
+    distinct
+}
+
+/// Find all files under `directories` using a pool of walker threads, on top of the
+/// `found` ones already known from a resumed run.
+/// Each walker takes a directory from the shared queue, reads it, and queues the
+/// directories found inside when running recursively. Files are stored within the
+/// configured memory limit, and later read back sorted, so results do not depend on
+/// which walker got to them first. The state of the walk is saved to `checkpoint`
+/// periodically, and once it completes or is interrupted.
+/// Also returns whether the walk stopped early because `--max-files` were found.
+pub fn walk(
+    directories: Vec<PathBuf>,
+    found: Vec<FileEntry>,
+    config: &Config,
+    checkpoint: Option<&Checkpoint>,
+) -> (FileStore, bool) {
+    PROGRESS
+        .files_found
+        .fetch_add(found.len() as u64, Ordering::Relaxed);
+    let mut files = FileStore::new(config.memory_limit);
+    files.extend(found);
+    let queue = WorkQueue {
+        state: Mutex::new(QueueState {
+            directories: VecDeque::from(directories),
+            in_progress: Vec::new(),
+            files,
+        }),
+        changed: Condvar::new(),
+        budget: FileBudget::new(config.max_files),
+    };
+    // Directories already queued count as seen, a link to one of them must not queue it again.
+    let visited = config.follow_symlinks.then(|| {
+        let visited = VisitedDirectories::new();
+        let state = queue.state.lock().unwrap_or_else(PoisonError::into_inner);
+        for directory in &state.directories {
+            if let Ok(meta) = directory.metadata() {
+                visited.first_visit(directory, &meta);
+            }
+        }
+        visited
+    });
+    let ignore_rules = (config.respect_gitignore || config.fdupignore)
+        .then(|| IgnoreRules::new(config.respect_gitignore, config.fdupignore));
+    thread::scope(|scope| {
+        for _ in 0..config.threads {
+            scope.spawn(|| {
+                while let Some(directory) = queue.next() {
+                    let mut subdirectories = Vec::new();
+                    let mut files = Vec::new();
+                    if let Err(err) = walk_directory(
+                        &directory,
+                        &mut subdirectories,
+                        &mut files,
+                        config,
+                        visited.as_ref(),
+                        &queue.budget,
+                        ignore_rules.as_ref(),
+                    ) {
+                        log::error!(
+                            "Error walking directory: `{}` {err}",
+                            directory.to_string_lossy()
+                        );
+                    }
+                    // A directory read only partly must be read again when resuming.
+                    if interrupt::interrupted() {
+                        queue.abandon(directory);
+                        continue;
+                    }
+                    // We may need to run recursively, unless no more files can be taken in
+                    if !config.recurse || queue.budget.exceeded() {
+                        subdirectories.clear();
+                    }
+                    queue.finish(&directory, subdirectories, files);
+
+                    if let Some(checkpoint) = checkpoint
+                        && checkpoint.due()
+                    {
+                        let (pending, files) = queue.snapshot();
+                        checkpoint.save_walk(&pending, &files);
+                    }
+                }
+            });
+        }
+    });
+
+    let truncated = queue.budget.exceeded();
+    let state = queue
+        .state
+        .into_inner()
+        .unwrap_or_else(PoisonError::into_inner);
+    if let Some(checkpoint) = checkpoint {
+        let pending: Vec<PathBuf> = state.directories.into();
+        checkpoint.save_walk(&pending, state.files.in_memory());
+    }
+    (state.files, truncated)
+}
+
+/// The scan root `path` was found under.
+pub fn root_of<'a>(path: &Path, roots: &'a [PathBuf]) -> Option<&'a Path> {
+    roots
+        .iter()
+        .find(|root| path.starts_with(root))
+        .map(PathBuf::as_path)
+}
+
+/// `path` from the scan root it was found under, as patterns are matched against it.
+pub fn relative_to_root<'a>(path: &'a Path, roots: &[PathBuf]) -> &'a Path {
+    roots
+        .iter()
+        .find_map(|root| path.strip_prefix(root).ok())
+        .unwrap_or(path)
+}
+
+/// Walk a given directory.
+/// # Arguments
+/// `path` the directory being analyzed
+/// `subdirectories` list to store all directories found. Used in recursive execution.
+/// `files`  list storing all files found, hashed later on.
+/// `config` options passed to the CLI
+/// `visited` directories seen so far, when following symlinks
+/// `budget` files left to find before stopping
+/// `ignore_rules` ignore files to respect, if any
+fn walk_directory(
+    path: impl AsRef<Path>,
+    subdirectories: &mut Vec<PathBuf>,
+    files: &mut Vec<FileEntry>,
+    config: &Config,
+    visited: Option<&VisitedDirectories>,
+    budget: &FileBudget,
+    ignore_rules: Option<&IgnoreRules>,
+) -> std::io::Result<()> {
+    // Ignore files are matched against absolute paths, so those above the root apply too.
+    let ignored = match ignore_rules {
+        Some(ignore_rules) => {
+            let directory = std::path::absolute(path.as_ref())?;
+            Some((ignore_rules.for_directory(&directory), directory))
+        }
+        None => None,
+    };
+    // Subdirectories on another device are mount points, not walked into when asked so.
+    let device = match config.one_file_system {
+        true => platform::device_id(path.as_ref(), &std::fs::metadata(path.as_ref())?),
+        false => None,
+    };
+    let _permit = open_files::acquire(1);
+    log::debug!("Reading directory: `{}`", path.as_ref().to_string_lossy());
+    let directory_iterator = std::fs::read_dir(path)?;
+    PROGRESS.directories.fetch_add(1, Ordering::Relaxed);
+    let include_hidden = config.include_hidden;
+    for dir_item in directory_iterator.flatten() {
+        if interrupt::interrupted() {
+            break;
+        }
+        let item_path = dir_item.path();
+
+        // Symlinks and junctions are left alone unless asked for, whatever they point to.
+        if visited.is_none() && platform::is_link(&dir_item) {
+            continue;
+        }
+
+        let meta = match item_path.metadata() {
+            Ok(meta) => meta,
+            Err(err) => {
+                log::error!(
+                    "Error reading metadata: `{}` {err}",
+                    item_path.to_string_lossy()
+                );
+                continue;
+            }
+        };
+
+        // Check if hidden files are to be ignored
+        if !include_hidden && platform::is_hidden(&item_path, &meta) {
+            continue;
+        }
+
+        if let Some((rules, directory)) = &ignored {
+            let is_git_directory =
+                config.respect_gitignore && meta.is_dir() && dir_item.file_name() == ".git";
+            if is_git_directory
+                || rules.ignored(&directory.join(dir_item.file_name()), meta.is_dir())
+            {
+                continue;
+            }
+        }
+
+        let relative = relative_to_root(&item_path, &config.roots);
+        if meta.is_dir() {
+            let too_deep = config
+                .max_depth
+                .is_some_and(|depth| relative.components().count() > depth);
+            let other_device =
+                device.is_some_and(|device| platform::device_id(&item_path, &meta) != Some(device));
+            let opaque = config.skip_bundles && platform::is_bundle(&item_path);
+            if !too_deep
+                && !other_device
+                && !opaque
+                && !config.filters.excludes_directory(&item_path, relative)
+                && visited.is_none_or(|visited| visited.first_visit(&item_path, &meta))
+            {
+                subdirectories.push(item_path);
+            }
+        } else if !meta.is_file() {
+            // Devices, sockets and FIFOs may block forever when read, and hold no contents to compare.
+            match config.warn_special {
+                true => log::warn!("Skipping special file: `{}`", item_path.to_string_lossy()),
+                false => log::info!("Skipping special file: `{}`", item_path.to_string_lossy()),
+            }
+        } else {
+            if !accepts(config, &item_path, relative, &meta) {
+                continue;
+            }
+            if !budget.take() {
+                break;
+            }
+            PROGRESS.files_found.fetch_add(1, Ordering::Relaxed);
+            files.push(FileEntry::new(item_path, &meta));
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether the file at `path`, `relative` to its scan root, passes the filters of `config`.
+fn accepts(config: &Config, path: &Path, relative: &Path, meta: &Metadata) -> bool {
+    config.filters.accepts_size(meta.len())
+        && config.filters.accepts_modified(meta.modified().ok())
+        && config.filters.accepts_owner(platform::owner(meta))
+        && config.filters.accepts_extension(path)
+        && config.filters.accepts_file(relative)
+}
+
+/// Files listed at `source` by `--files-from`, `-` standing for stdin, to be compared
+/// instead of walking the path. Paths are separated by NUL characters if there are any,
+/// by line breaks otherwise, and filtered the way walked files are. Returns whether some
+/// were left out by `--max-files`.
+pub fn read_file_list(source: &Path, config: &Config) -> io::Result<(Vec<FileEntry>, bool)> {
+    let mut list = Vec::new();
+    match source.as_os_str() == "-" {
+        true => io::stdin().lock().read_to_end(&mut list)?,
+        false => fs::File::open(source)?.read_to_end(&mut list)?,
+    };
+    let separator = match list.contains(&0) {
+        true => b'\0',
+        false => b'\n',
+    };
+    let mut files = Vec::new();
+    let mut listed = HashSet::new();
+    for entry in list.split(|&byte| byte == separator) {
+        // Lists written on Windows end their lines with a carriage return too.
+        let entry = match separator {
+            b'\n' => entry.strip_suffix(b"\r").unwrap_or(entry),
+            _ => entry,
+        };
+        if entry.is_empty() {
+            continue;
+        }
+        let path = platform::path_from_bytes(entry);
+        if !listed.insert(path.clone()) {
+            continue;
+        }
+        let meta = match path.metadata() {
+            Ok(meta) => meta,
+            Err(err) => {
+                log::error!("Error reading metadata: `{}` {err}", path.to_string_lossy());
+                continue;
+            }
+        };
+        if !meta.is_file() {
+            log::warn!("Skipping `{}`, it is not a file", path.to_string_lossy());
+            continue;
+        }
+        if !accepts(config, &path, relative_to_root(&path, &config.roots), &meta) {
+            continue;
+        }
+        if config
+            .max_files
+            .is_some_and(|max| files.len() as u64 >= max)
+        {
+            return Ok((files, true));
+        }
+        files.push(FileEntry::new(path, &meta));
+    }
+    Ok((files, false))
+}